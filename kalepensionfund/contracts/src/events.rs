@@ -0,0 +1,295 @@
+//! Events published by `KalePensionFund`.
+//!
+//! Topic symbols and data layouts are part of the contract ABI; indexers can
+//! rely on them staying stable. `topic[0]` always identifies the event kind.
+//! Amounts by token are `Map<Address, i128>` keyed by token address.
+//!
+//! | topic[0]    | topic[1]             | data                                  |
+//! |-------------|----------------------|---------------------------------------|
+//! | `init`      | admin                | `ConfigEvent`                         |
+//! | `config`    | `oracle` / `router`  | new address                           |
+//! | `config`    | `guardian`           | new guardian                          |
+//! | `config`    | `asset`, token       | `AssetConfig`                         |
+//! | `config`    | `policy`             | `RetirementPolicy`                    |
+//! | `config`    | `transfers`          | `TransferPolicy`                      |
+//! | `config`    | `fees`               | `FeeConfig`                           |
+//! | `config`    | `profile`, id        | `RiskProfile`                         |
+//! | `config`    | `glide`              | `GlidePath`                           |
+//! | `config`    | `drift`              | drift band in bps                     |
+//! | `config`    | `averaging`          | `AveragingSource`                     |
+//! | `employer`  | employer             | `EmployerTerms`                       |
+//! | `employer`  | employer, `removed`  | `()`                                  |
+//! | `admin`     | `proposed`           | proposed admin                        |
+//! | `admin`     | `accepted`           | new admin                             |
+//! | `admin`     | `upgraded`           | new wasm hash                         |
+//! | `admin`     | `migrated`           | `(storage_version, migrated)`         |
+//! | `deposit`   | user                 | `(token, amount)`                     |
+//! | `withdraw`  | user                 | amounts sent by token                 |
+//! | `risk`      | user                 | `(old_level, new_level)`              |
+//! | `retire`    | user                 | `(old_date, new_date)`                |
+//! | `hardship`  | user                 | `()`                                  |
+//! | `penalty`   | user                 | `(reserve, amounts by token)`         |
+//! | `annuity`   | user, `set`          | `PayoutSchedule`                      |
+//! | `annuity`   | user, `cancel`       | `()`                                  |
+//! | `payout`    | user                 | `(token, amount, installments)`       |
+//! | `heirs`     | user                 | `(Vec<Beneficiary>, inactivity)`      |
+//! | `inherit`   | member, beneficiary  | amounts sent by token                 |
+//! | `contrib`   | employer, employee   | `(token, amount, match_amount)`       |
+//! | `vest`      | user                 | shares newly vested                   |
+//! | `forfeit`   | user                 | `(employer, amounts by token)`        |
+//! | `fees`      | treasury, risk level | `(shares, amounts by token)` collected|
+//! | `transfer`  | from, to             | shares moved (SEP-41)                 |
+//! | `approve`   | from, spender        | `(amount, expiration_ledger)` (SEP-41)|
+//! | `swap`      | user                 | `SwapEvent`                           |
+//! | `swap`      | user, `failed`       | `SwapEvent` skipped, `amount_out` 0   |
+//! | `rebalance` | user                 | `RebalanceEvent`                      |
+//! | `pause`     | `Operation`          | `true` paused / `false` lifted        |
+//! | `pause`     | `shortfall`          | `Vec<Solvency>` (all paused)          |
+//! | `circuit`   | `open` / `close`     | consecutive oracle failures           |
+//! | `fallback`  | pair                 | `(price, PriceSource)`                |
+
+use soroban_sdk::{contracttype, symbol_short, Address, BytesN, Env, Map, Symbol, Vec};
+
+use crate::{AssetConfig, AveragingSource, Beneficiary, EmployerTerms, FeeConfig, GlidePath, Operation, PayoutSchedule, PriceSource, RetirementPolicy, RiskProfile, Solvency, TransferPolicy};
+
+pub const INIT: Symbol = symbol_short!("init");
+pub const CONFIG: Symbol = symbol_short!("config");
+pub const ADMIN: Symbol = symbol_short!("admin");
+pub const DEPOSIT: Symbol = symbol_short!("deposit");
+pub const WITHDRAW: Symbol = symbol_short!("withdraw");
+pub const RISK: Symbol = symbol_short!("risk");
+pub const RETIRE: Symbol = symbol_short!("retire");
+pub const HARDSHIP: Symbol = symbol_short!("hardship");
+pub const PENALTY: Symbol = symbol_short!("penalty");
+pub const ANNUITY: Symbol = symbol_short!("annuity");
+pub const PAYOUT: Symbol = symbol_short!("payout");
+pub const HEIRS: Symbol = symbol_short!("heirs");
+pub const INHERIT: Symbol = symbol_short!("inherit");
+pub const EMPLOYER: Symbol = symbol_short!("employer");
+pub const CONTRIB: Symbol = symbol_short!("contrib");
+pub const VEST: Symbol = symbol_short!("vest");
+pub const FORFEIT: Symbol = symbol_short!("forfeit");
+pub const FEES: Symbol = symbol_short!("fees");
+pub const TRANSFER: Symbol = symbol_short!("transfer");
+pub const APPROVE: Symbol = symbol_short!("approve");
+pub const SWAP: Symbol = symbol_short!("swap");
+pub const REBALANCE: Symbol = symbol_short!("rebalance");
+pub const PAUSE: Symbol = symbol_short!("pause");
+pub const CIRCUIT: Symbol = symbol_short!("circuit");
+pub const FALLBACK: Symbol = symbol_short!("fallback");
+
+pub const ORACLE: Symbol = symbol_short!("oracle");
+pub const ROUTER: Symbol = symbol_short!("router");
+pub const GUARDIAN: Symbol = symbol_short!("guardian");
+pub const ASSET: Symbol = symbol_short!("asset");
+pub const POLICY: Symbol = symbol_short!("policy");
+pub const TRANSFERS: Symbol = symbol_short!("transfers");
+pub const PROFILE: Symbol = symbol_short!("profile");
+pub const GLIDE: Symbol = symbol_short!("glide");
+pub const DRIFT: Symbol = symbol_short!("drift");
+pub const AVERAGING: Symbol = symbol_short!("averaging");
+pub const PROPOSED: Symbol = symbol_short!("proposed");
+pub const ACCEPTED: Symbol = symbol_short!("accepted");
+pub const UPGRADED: Symbol = symbol_short!("upgraded");
+pub const MIGRATED: Symbol = symbol_short!("migrated");
+pub const OPEN: Symbol = symbol_short!("open");
+pub const CLOSE: Symbol = symbol_short!("close");
+pub const SET: Symbol = symbol_short!("set");
+pub const CANCEL: Symbol = symbol_short!("cancel");
+pub const REMOVED: Symbol = symbol_short!("removed");
+pub const FAILED: Symbol = symbol_short!("failed");
+pub const SHORTFALL: Symbol = symbol_short!("shortfall");
+
+// Addresses configured at initialization
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct ConfigEvent {
+    pub kale_token: Address,
+    pub usdc_token: Address,
+    pub btc_token: Address,
+    pub oracle: Address,
+    pub router: Address,
+}
+
+// A single router swap executed during rebalance
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct SwapEvent {
+    pub token_in: Address,
+    pub token_out: Address,
+    pub amount_in: i128,
+    pub amount_out: i128,
+    pub min_out: i128,
+}
+
+// Prices used by a rebalance and the resulting net pool balance changes, by token
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct RebalanceEvent {
+    pub prices: Map<Address, i128>,
+    pub deltas: Map<Address, i128>,
+}
+
+pub fn initialized(env: &Env, admin: &Address, config: ConfigEvent) {
+    env.events().publish((INIT, admin.clone()), config);
+}
+
+pub fn address_changed(env: &Env, field: Symbol, address: &Address) {
+    env.events().publish((CONFIG, field), address.clone());
+}
+
+pub fn asset_changed(env: &Env, token: &Address, config: AssetConfig) {
+    env.events().publish((CONFIG, ASSET, token.clone()), config);
+}
+
+pub fn policy_changed(env: &Env, policy: RetirementPolicy) {
+    env.events().publish((CONFIG, POLICY), policy);
+}
+
+pub fn transfer_policy_changed(env: &Env, policy: TransferPolicy) {
+    env.events().publish((CONFIG, TRANSFERS), policy);
+}
+
+pub fn risk_profile_changed(env: &Env, profile_id: u32, profile: RiskProfile) {
+    env.events().publish((CONFIG, PROFILE, profile_id), profile);
+}
+
+pub fn glide_path_changed(env: &Env, glide_path: GlidePath) {
+    env.events().publish((CONFIG, GLIDE), glide_path);
+}
+
+pub fn drift_band_changed(env: &Env, drift_band_bps: u32) {
+    env.events().publish((CONFIG, DRIFT), drift_band_bps);
+}
+
+pub fn averaging_source_changed(env: &Env, source: AveragingSource) {
+    env.events().publish((CONFIG, AVERAGING), source);
+}
+
+pub fn fees_changed(env: &Env, config: FeeConfig) {
+    env.events().publish((CONFIG, FEES), config);
+}
+
+pub fn employer_terms(env: &Env, employer: &Address, terms: EmployerTerms) {
+    env.events().publish((EMPLOYER, employer.clone()), terms);
+}
+
+pub fn employer_removed(env: &Env, employer: &Address) {
+    env.events().publish((EMPLOYER, employer.clone(), REMOVED), ());
+}
+
+pub fn admin_proposed(env: &Env, new_admin: &Address) {
+    env.events().publish((ADMIN, PROPOSED), new_admin.clone());
+}
+
+pub fn admin_accepted(env: &Env, new_admin: &Address) {
+    env.events().publish((ADMIN, ACCEPTED), new_admin.clone());
+}
+
+pub fn upgraded(env: &Env, new_wasm_hash: BytesN<32>) {
+    env.events().publish((ADMIN, UPGRADED), new_wasm_hash);
+}
+
+pub fn migrated(env: &Env, storage_version: u32, migrated: u32) {
+    env.events().publish((ADMIN, MIGRATED), (storage_version, migrated));
+}
+
+pub fn deposit(env: &Env, user: &Address, token: &Address, amount: i128) {
+    env.events().publish((DEPOSIT, user.clone()), (token.clone(), amount));
+}
+
+pub fn withdraw(env: &Env, user: &Address, amounts: Map<Address, i128>) {
+    env.events().publish((WITHDRAW, user.clone()), amounts);
+}
+
+pub fn risk_changed(env: &Env, user: &Address, old_level: u32, new_level: u32) {
+    env.events().publish((RISK, user.clone()), (old_level, new_level));
+}
+
+pub fn retirement_date_set(env: &Env, user: &Address, old_date: u64, new_date: u64) {
+    env.events().publish((RETIRE, user.clone()), (old_date, new_date));
+}
+
+pub fn hardship_approved(env: &Env, user: &Address) {
+    env.events().publish((HARDSHIP, user.clone()), ());
+}
+
+pub fn early_penalty(env: &Env, user: &Address, reserve: &Address, amounts: Map<Address, i128>) {
+    env.events().publish((PENALTY, user.clone()), (reserve.clone(), amounts));
+}
+
+pub fn payout_scheduled(env: &Env, user: &Address, schedule: PayoutSchedule) {
+    env.events().publish((ANNUITY, user.clone(), SET), schedule);
+}
+
+pub fn payout_cancelled(env: &Env, user: &Address) {
+    env.events().publish((ANNUITY, user.clone(), CANCEL), ());
+}
+
+pub fn payout(env: &Env, user: &Address, token: &Address, amount: i128, installments: u32) {
+    env.events().publish((PAYOUT, user.clone()), (token.clone(), amount, installments));
+}
+
+pub fn beneficiaries_changed(env: &Env, user: &Address, beneficiaries: Vec<Beneficiary>, inactivity_period: u64) {
+    env.events().publish((HEIRS, user.clone()), (beneficiaries, inactivity_period));
+}
+
+pub fn inheritance(env: &Env, member: &Address, beneficiary: &Address, amounts: Map<Address, i128>) {
+    env.events().publish((INHERIT, member.clone(), beneficiary.clone()), amounts);
+}
+
+pub fn contribution(env: &Env, employer: &Address, employee: &Address, token: &Address, amount: i128, match_amount: i128) {
+    env.events().publish((CONTRIB, employer.clone(), employee.clone()), (token.clone(), amount, match_amount));
+}
+
+pub fn vested(env: &Env, user: &Address, shares: i128) {
+    env.events().publish((VEST, user.clone()), shares);
+}
+
+pub fn forfeited(env: &Env, user: &Address, employer: &Address, amounts: Map<Address, i128>) {
+    env.events().publish((FORFEIT, user.clone()), (employer.clone(), amounts));
+}
+
+pub fn fees_collected(env: &Env, treasury: &Address, risk_level: u32, shares: i128, amounts: Map<Address, i128>) {
+    env.events().publish((FEES, treasury.clone(), risk_level), (shares, amounts));
+}
+
+pub fn shares_transferred(env: &Env, from: &Address, to: &Address, amount: i128) {
+    env.events().publish((TRANSFER, from.clone(), to.clone()), amount);
+}
+
+pub fn shares_approved(env: &Env, from: &Address, spender: &Address, amount: i128, expiration_ledger: u32) {
+    env.events().publish((APPROVE, from.clone(), spender.clone()), (amount, expiration_ledger));
+}
+
+pub fn swap(env: &Env, user: &Address, swap: SwapEvent) {
+    env.events().publish((SWAP, user.clone()), swap);
+}
+
+pub fn swap_failed(env: &Env, user: &Address, swap: SwapEvent) {
+    env.events().publish((SWAP, user.clone(), FAILED), swap);
+}
+
+pub fn rebalanced(env: &Env, user: &Address, rebalance: RebalanceEvent) {
+    env.events().publish((REBALANCE, user.clone()), rebalance);
+}
+
+pub fn paused_on_shortfall(env: &Env, report: Vec<Solvency>) {
+    env.events().publish((PAUSE, SHORTFALL), report);
+}
+
+pub fn pause_changed(env: &Env, operation: Operation, paused: bool) {
+    env.events().publish((PAUSE, operation), paused);
+}
+
+pub fn circuit_opened(env: &Env, consecutive_failures: u32) {
+    env.events().publish((CIRCUIT, OPEN), consecutive_failures);
+}
+
+pub fn circuit_closed(env: &Env, consecutive_failures: u32) {
+    env.events().publish((CIRCUIT, CLOSE), consecutive_failures);
+}
+
+pub fn price_fallback(env: &Env, pair: &Symbol, price: i128, source: PriceSource) {
+    env.events().publish((FALLBACK, pair.clone()), (price, source));
+}
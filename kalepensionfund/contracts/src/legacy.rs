@@ -0,0 +1,229 @@
+//! Storage layouts of earlier versions, read only by `migrate`.
+//!
+//! Version 1 portfolios held their balances directly. Version 2 moved them into
+//! pools but kept every balance and target in fixed KALE, USDC and BTC fields, and
+//! named the three tokens through their own instance keys. Those fixed slots map
+//! onto the registered assets priced as `KALE_USD`, `USDC_USD` and `BTC_USD`.
+
+use soroban_sdk::{contracttype, Address, Env, Map, String, Symbol, TryFromVal, Val};
+
+use crate::{DataKey, Error, KalePensionFund, PayoutAmount, PayoutSchedule, Pool, Portfolio, RiskProfile};
+
+// Storage version 1 portfolio
+#[derive(Clone)]
+#[contracttype(export = false)]
+pub struct PortfolioV1 {
+    pub user: Address,
+    pub kale_balance: i128,
+    pub usdc_balance: i128,
+    pub btc_balance: i128,
+    pub risk_level: u32,
+}
+
+// Storage version 2 portfolio; its balances were derived from shares and are not carried over
+#[derive(Clone)]
+#[contracttype(export = false)]
+pub struct PortfolioV2 {
+    pub user: Address,
+    pub shares: i128,
+    pub kale_balance: i128,
+    pub usdc_balance: i128,
+    pub btc_balance: i128,
+    pub risk_level: u32,
+    pub retirement_date: u64,
+    pub hardship_approved: bool,
+    pub last_activity: u64,
+    pub own_contributions: i128,
+    pub employer_shares: i128,
+    pub vested_bps: u32,
+}
+
+#[derive(Clone)]
+#[contracttype(export = false)]
+pub struct PoolV2 {
+    pub kale_balance: i128,
+    pub usdc_balance: i128,
+    pub btc_balance: i128,
+    pub total_shares: i128,
+    pub fee_shares: i128,
+    pub fees_accrued_at: u64,
+    pub high_water_mark: i128,
+}
+
+#[derive(Clone)]
+#[contracttype(export = false)]
+pub struct RiskProfileV2 {
+    pub name: String,
+    pub kale_bps: u32,
+    pub usdc_bps: u32,
+    pub btc_bps: u32,
+}
+
+#[derive(Clone)]
+#[contracttype(export = false)]
+pub struct LiabilitiesV2 {
+    pub kale_balance: i128,
+    pub usdc_balance: i128,
+    pub btc_balance: i128,
+}
+
+// Fixed asset slot of version 2
+#[derive(Clone, Copy)]
+#[contracttype(export = false)]
+pub enum TokenKind {
+    Kale,
+    Usdc,
+    Btc,
+}
+
+#[derive(Clone)]
+#[contracttype(export = false)]
+pub struct PayoutScheduleV2 {
+    pub asset: TokenKind,
+    pub amount: PayoutAmount,
+    pub interval: u64,
+    pub next_payout: u64,
+}
+
+// Instance keys that named the USDC and BTC tokens before the asset registry
+#[derive(Clone)]
+#[contracttype(export = false)]
+pub enum DataKeyV2 {
+    UsdcTokenAddress,
+    BtcTokenAddress,
+}
+
+// Build the asset registry from the version 2 token keys and convert the fund-wide
+// data: risk profiles, pools and liabilities. Does nothing once the registry exists.
+pub fn migrate_config(env: &Env) -> Result<(), Error> {
+    let storage = env.storage().instance();
+    if storage.has(&DataKey::Assets) {
+        return Ok(());
+    }
+
+    let kale_token: Address = storage.get(&DataKey::KaleTokenAddress).ok_or(Error::NotInitialized)?;
+    let usdc_token: Address = storage.get(&DataKeyV2::UsdcTokenAddress).ok_or(Error::NotInitialized)?;
+    let btc_token: Address = storage.get(&DataKeyV2::BtcTokenAddress).ok_or(Error::NotInitialized)?;
+    storage.set(&DataKey::Assets, &KalePensionFund::default_assets(env, &kale_token, &usdc_token, &btc_token)?);
+    storage.remove(&DataKeyV2::UsdcTokenAddress);
+    storage.remove(&DataKeyV2::BtcTokenAddress);
+    let slots = [kale_token, usdc_token, btc_token];
+
+    // Version 1 had fixed tiers instead of stored profiles
+    let profiles = match storage.get::<DataKey, Map<u32, RiskProfileV2>>(&DataKey::RiskProfiles) {
+        Some(old) => {
+            let mut profiles = Map::new(env);
+            for (profile_id, profile) in old.iter() {
+                profiles.set(profile_id, RiskProfile {
+                    name: profile.name,
+                    targets: slot_map(env, &slots, [profile.kale_bps, profile.usdc_bps, profile.btc_bps]),
+                });
+            }
+            profiles
+        }
+        None => KalePensionFund::default_risk_profiles(env, &slots[0], &slots[1], &slots[2]),
+    };
+    storage.set(&DataKey::RiskProfiles, &profiles);
+
+    for pool_id in KalePensionFund::pool_ids(env) {
+        let key = DataKey::Pool(pool_id);
+        if let Some(old) = env.storage().persistent().get::<DataKey, PoolV2>(&key) {
+            env.storage().persistent().set(&key, &Pool {
+                balances: slot_map(env, &slots, [old.kale_balance, old.usdc_balance, old.btc_balance]),
+                total_shares: old.total_shares,
+                fee_shares: old.fee_shares,
+                fees_accrued_at: old.fees_accrued_at,
+                high_water_mark: old.high_water_mark,
+            });
+        }
+    }
+
+    if let Some(old) = storage.get::<DataKey, LiabilitiesV2>(&DataKey::Liabilities) {
+        let liabilities = slot_map(env, &slots, [old.kale_balance, old.usdc_balance, old.btc_balance]);
+        storage.set(&DataKey::Liabilities, &liabilities);
+    }
+    Ok(())
+}
+
+// Convert one member's portfolio, and payout schedule, to the current layout.
+// Returns false if there was nothing to convert.
+pub fn migrate_member(env: &Env, member: &Address) -> Result<bool, Error> {
+    let key = DataKey::Portfolio(member.clone());
+    // Each layout is told apart by the field it introduced
+    let fields: Map<Symbol, Val> = match env.storage().persistent().get(&key) {
+        Some(fields) => fields,
+        None => return Ok(false),
+    };
+    if fields.contains_key(Symbol::new(env, "balances")) {
+        return Ok(false);
+    }
+
+    let slots = slot_tokens(env)?;
+    if fields.contains_key(Symbol::new(env, "shares")) {
+        let old = PortfolioV2::try_from_val(env, &fields.to_val()).map_err(|_| Error::InvalidConfig)?;
+        let portfolio = Portfolio {
+            user: old.user,
+            shares: old.shares,
+            balances: Map::new(env),
+            risk_level: old.risk_level,
+            retirement_date: old.retirement_date,
+            hardship_approved: old.hardship_approved,
+            last_activity: old.last_activity,
+            own_contributions: old.own_contributions,
+            employer_shares: old.employer_shares,
+            vested_bps: old.vested_bps,
+        };
+        env.storage().persistent().set(&key, &portfolio);
+
+        let schedule_key = DataKey::PayoutSchedule(member.clone());
+        if let Some(old) = env.storage().persistent().get::<DataKey, PayoutScheduleV2>(&schedule_key) {
+            env.storage().persistent().set(&schedule_key, &PayoutSchedule {
+                asset: slots[old.asset as usize].clone(),
+                amount: old.amount,
+                interval: old.interval,
+                next_payout: old.next_payout,
+            });
+        }
+        return Ok(true);
+    }
+
+    // Version 1 balances are deposited into the member's pool
+    let old = PortfolioV1::try_from_val(env, &fields.to_val()).map_err(|_| Error::InvalidConfig)?;
+    let mut portfolio = Portfolio::new(member.clone());
+    portfolio.risk_level = old.risk_level;
+    let mut pool = KalePensionFund::load_pool(env, portfolio.risk_level)?;
+    let amounts = slot_map(env, &slots, [old.kale_balance, old.usdc_balance, old.btc_balance]);
+    for (token, amount) in amounts.iter() {
+        if amount > 0 {
+            portfolio.own_contributions += KalePensionFund::asset_value(env, &token, amount)?;
+        }
+    }
+    portfolio.shares = KalePensionFund::mint_shares(env, &mut pool, amounts)?;
+    KalePensionFund::touch(env, &mut portfolio);
+    KalePensionFund::save_portfolio(env, &mut portfolio, &pool)?;
+    Ok(true)
+}
+
+// Registered tokens filling the KALE, USDC and BTC slots
+fn slot_tokens(env: &Env) -> Result<[Address; 3], Error> {
+    let assets = KalePensionFund::get_assets(env.clone());
+    let token = |pair: &str| {
+        let pair = Symbol::new(env, pair);
+        assets.iter()
+            .find(|(_, asset)| asset.price_pair == pair)
+            .map(|(token, _)| token)
+            .ok_or(Error::UnsupportedAsset)
+    };
+    Ok([token("KALE_USD")?, token("USDC_USD")?, token("BTC_USD")?])
+}
+
+fn slot_map<V>(env: &Env, slots: &[Address; 3], values: [V; 3]) -> Map<Address, V>
+where
+    V: soroban_sdk::IntoVal<Env, Val> + TryFromVal<Env, Val>,
+{
+    let mut map = Map::new(env);
+    for (token, value) in slots.iter().zip(values) {
+        map.set(token.clone(), value);
+    }
+    map
+}
@@ -1,13 +1,23 @@
 #![no_std]
 
 use soroban_sdk::{
-    contract, contractimpl, contracttype, token, Address, Env, Symbol, Vec, String, IntoVal, log
+    contract, contracterror, contractimpl, contracttype, token, Address, BytesN, Env, Map, Symbol, Vec, String, IntoVal, log
 };
 
+use soroban_sdk::auth::{ContractContext, InvokerContractAuthEntry, SubContractInvocation};
+
+use math::Rounding;
+
+mod events;
+mod legacy;
+mod math;
+mod share_token;
+
 // Production-ready Reflector Oracle interface (SEP-40 compatible)
 mod reflector {
     use soroban_sdk::{contracttype, contracterror, Address, Env, Symbol, Vec};
 
+    #[allow(dead_code)]
     #[soroban_sdk::contractclient(name = "ReflectorClient")]
     pub trait Contract {
         /// Get base asset for pricing
@@ -44,7 +54,8 @@ mod reflector {
         fn admin(e: Env) -> Option<Address>;
     }
 
-    #[contracttype(export = false)]
+    // Exported: the asset registry stores how the oracle identifies each fund asset
+    #[contracttype]
     #[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd)]
     pub enum Asset {
         Stellar(Address),
@@ -76,23 +87,56 @@ mod reflector {
 #[soroban_sdk::contractclient(name = "MockOracleClient")]
 pub trait MockOracle {
     fn set_price(e: Env, asset: Symbol, price: i128);
+    fn set_decimals(e: Env, decimals: u32);
+    fn decimals(e: Env) -> u32;
+    fn set_twap(e: Env, asset: Symbol, price: i128);
+    fn twap(e: Env, asset: reflector::Asset, records: u32) -> Option<i128>;
     fn lastprice(e: Env, asset: reflector::Asset) -> Option<reflector::PriceData>;
 }
 
+// Test builds only, so the mock's functions are never exported from the fund's wasm
+#[cfg(test)]
 #[contract]
 pub struct MockOracleContract;
 
+#[cfg(test)]
+impl MockOracleContract {
+    fn asset_symbol(env: &Env, asset: reflector::Asset) -> Symbol {
+        match asset {
+            reflector::Asset::Other(sym) => sym,
+            reflector::Asset::Stellar(_) => Symbol::new(env, "KALE"), // Default for testing
+        }
+    }
+}
+
+#[cfg(test)]
 #[contractimpl]
 impl MockOracle for MockOracleContract {
     fn set_price(env: Env, asset: Symbol, price: i128) {
         env.storage().persistent().set(&asset, &price);
     }
 
+    fn set_decimals(env: Env, decimals: u32) {
+        env.storage().instance().set(&Symbol::new(&env, "decimals"), &decimals);
+    }
+
+    // Prices are set at PRICE_SCALE unless told otherwise
+    fn decimals(env: Env) -> u32 {
+        env.storage().instance().get(&Symbol::new(&env, "decimals")).unwrap_or(PRICE_DECIMALS)
+    }
+
+    fn set_twap(env: Env, asset: Symbol, price: i128) {
+        env.storage().persistent().set(&(Symbol::new(&env, "twap"), asset), &price);
+    }
+
+    // Same TWAP whatever the number of records; None until one is set
+    fn twap(env: Env, asset: reflector::Asset, _records: u32) -> Option<i128> {
+        let symbol = Self::asset_symbol(&env, asset);
+        env.storage().persistent().get(&(Symbol::new(&env, "twap"), symbol))
+    }
+
     fn lastprice(env: Env, asset: reflector::Asset) -> Option<reflector::PriceData> {
-        let symbol = match asset {
-            reflector::Asset::Other(sym) => sym,
-            reflector::Asset::Stellar(_) => Symbol::new(&env, "KALE"), // Default for testing
-        };
+        let symbol = Self::asset_symbol(&env, asset);
 
         if let Some(price) = env.storage().persistent().get(&symbol) {
             // Return a proper timestamp to pass validation
@@ -129,36 +173,495 @@ impl<'a> SoroswapRouterClient<'a> {
         to: Address,
         deadline: u64,
     ) -> Result<Vec<i128>, soroban_sdk::Error> {
-        // Call Soroswap router for token swap
+        // Call Soroswap router for token swap; a failed call is rolled back and reported
         let mut args = Vec::new(self.env);
         args.push_back(amount_in.into_val(self.env));
         args.push_back(amount_out_min.into_val(self.env));
         args.push_back(path.into_val(self.env));
         args.push_back(to.into_val(self.env));
         args.push_back(deadline.into_val(self.env));
-        self.env.invoke_contract(
-            self.address, 
+        match self.env.try_invoke_contract::<Vec<i128>, soroban_sdk::Error>(
+            self.address,
             &Symbol::new(self.env, "swap_exact_tokens_for_tokens"),
             args
-        )
+        ) {
+            Ok(Ok(amounts)) => Ok(amounts),
+            Ok(Err(error)) => Err(error.into()),
+            Err(Ok(error)) => Err(error),
+            Err(Err(_)) => Err(Error::SwapFailed.into()),
+        }
+    }
+
+    // Pair contract the router trades `token_a` for `token_b` through
+    pub fn try_router_pair_for(&self, token_a: &Address, token_b: &Address) -> Result<Address, soroban_sdk::Error> {
+        let mut args = Vec::new(self.env);
+        args.push_back(token_a.into_val(self.env));
+        args.push_back(token_b.into_val(self.env));
+        match self.env.try_invoke_contract::<Address, soroban_sdk::Error>(
+            self.address,
+            &Symbol::new(self.env, "router_pair_for"),
+            args
+        ) {
+            Ok(Ok(pair)) => Ok(pair),
+            Ok(Err(error)) => Err(error.into()),
+            Err(Ok(error)) => Err(error),
+            Err(Err(_)) => Err(Error::SwapFailed.into()),
+        }
     }
 }
 
+// Errors returned by KalePensionFund entrypoints
+#[contracterror]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
+#[repr(u32)]
+pub enum Error {
+    NotInitialized = 1,
+    InvalidAmount = 2,
+    InvalidRiskLevel = 3,
+    PortfolioNotFound = 4,
+    OracleUnavailable = 5,
+    SwapFailed = 6,
+    AlreadyInitialized = 7,
+    Unauthorized = 8,
+    UnsupportedAsset = 9,
+    InsufficientBalance = 10,
+    AlreadyEnrolled = 11,
+    RetirementLocked = 12,
+    InvalidRetirementDate = 13,
+    InvalidConfig = 14,
+    NoPayoutSchedule = 15,
+    PayoutNotDue = 16,
+    InvalidBeneficiaries = 17,
+    NoBeneficiaries = 18,
+    MemberStillActive = 19,
+    NotBeneficiary = 20,
+    EmployerNotRegistered = 21,
+    EmployedElsewhere = 22,
+    TransfersDisabled = 23,
+    RiskTierMismatch = 24,
+    InsufficientAllowance = 25,
+    BalanceMismatch = 26,
+    Paused = 27,
+    Overflow = 28,
+    DivisionByZero = 29,
+    PolicyNotSet = 30,
+    UnsupportedPair = 31,
+}
+
+// Fund asset registered by the admin. Holdings are keyed by the token's address,
+// so adding an asset needs no new code.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct AssetConfig {
+    pub price_pair: Symbol,               // Pair its price is cached and tracked under, e.g. KALE_USD
+    pub oracle_asset: reflector::Asset,   // How the oracle identifies the asset
+    pub decimals: u32,
+    pub enabled: bool, // Disabled assets take no new deposits or profile targets but can still be withdrawn
+}
+
+// Operations the guardian can halt independently; withdrawals can never be paused
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[contracttype]
+pub enum Operation {
+    Deposits, // Member deposits and employer contributions
+    Rebalances,
+    Swaps, // Every router swap, including conversions for payouts and fees
+}
+
+impl Operation {
+    pub const ALL: [Operation; 3] = [Operation::Deposits, Operation::Rebalances, Operation::Swaps];
+}
+
+// Oracle prices are cached for 5 minutes; after an outage a cached price serves for up
+// to an hour, and an open circuit breaker lets the oracle be tried again after 30 minutes
+const PRICE_CACHE_TTL: u32 = 300;
+const STALE_PRICE_TTL: u32 = 3_600;
+const CIRCUIT_RESET_TIME: u64 = 1_800;
+
+// Local price history keeps one bucket per hour for 7 days
+const HISTORY_BUCKET_SECONDS: u64 = 3_600;
+const HISTORY_WINDOW_SECONDS: u64 = 604_800;
+
+// Prices are normalized to PRICE_DECIMALS whatever decimals the oracle reports them
+// in, so PRICE_SCALE (1_000_000_000) == $1.00 for one whole token
+const PRICE_DECIMALS: u32 = 9;
+const PRICE_SCALE: i128 = 1_000_000_000;
+
+// Values are in USDC base units: USD with 7 decimals
+const VALUE_DECIMALS: u32 = 7;
+
+const SECONDS_PER_YEAR: u64 = 31_536_000;
+
+// Layout of the data this code reads and writes. Version 1 is the original layout,
+// where each `Portfolio` held its balances directly instead of pool shares; version 2
+// kept balances and targets in fixed KALE, USDC and BTC fields instead of maps keyed
+// by token. See `legacy` for both.
+pub const STORAGE_VERSION: u32 = 3;
+
+// Pool ids from here on are target-date pools: `TARGET_DATE_POOLS + retirement year`,
+// counting years of 365 days since the Unix epoch. Risk profile ids stay below it.
+pub const TARGET_DATE_POOLS: u32 = 1_000_000;
+
 #[derive(Clone)]
 #[contracttype]
 pub struct Portfolio {
     pub user: Address,
-    pub kale_balance: i128,
-    pub usdc_balance: i128,
-    pub btc_balance: i128,
-    pub risk_level: u32, // 1=conservative, 2=moderate, 3=aggressive
+    pub shares: i128, // Member-owned shares of the risk tier's pool
+    // Member's slice of the pool holdings by token, derived from `shares` whenever the portfolio is loaded
+    pub balances: Map<Address, i128>,
+    pub risk_level: u32, // Pool id: a risk profile (1=conservative, 2=moderate, 3=aggressive by default) or a target-date pool
+    pub retirement_date: u64, // Ledger timestamp when normal withdrawals unlock
+    pub hardship_approved: bool, // Admin waiver of the early-withdrawal penalty
+    pub last_activity: u64, // Last member action; drives the inheritance timer
+    pub own_contributions: i128, // USD value of the member's own deposits, for employer matching
+    pub employer_shares: i128, // Employer-funded shares not yet vested; vested ones move to `shares`
+    pub vested_bps: u32, // Share of employer funding vested so far (10000 = fully vested)
 }
 
-#[derive(Clone)]
+impl Portfolio {
+    // Empty portfolio with the default conservative profile
+    pub fn new(user: Address) -> Self {
+        Portfolio {
+            balances: Map::new(user.env()),
+            user,
+            shares: 0,
+            risk_level: 1,
+            retirement_date: 0,
+            hardship_approved: false,
+            last_activity: 0,
+            own_contributions: 0,
+            employer_shares: 0,
+            vested_bps: 0,
+        }
+    }
+
+    pub fn balance(&self, token: &Address) -> i128 {
+        self.balances.get(token.clone()).unwrap_or(0)
+    }
+
+    // Take shares out of the member's own, releasing the same fraction of their own
+    // contributions so the employer match only counts what is still invested
+    fn remove_shares(&mut self, shares: i128) -> Result<(), Error> {
+        if self.shares > 0 {
+            let released = math::mul_div_up(self.own_contributions, shares, self.shares)?;
+            self.own_contributions -= released.min(self.own_contributions);
+        }
+        self.shares -= shares;
+        Ok(())
+    }
+}
+
+// Assets held for every member of one risk tier; members own it through shares,
+// so a member's value is `shares * NAV per share`
+#[derive(Clone, Debug, Eq, PartialEq)]
 #[contracttype]
-pub struct PricePoint {
-    pub timestamp: u64,
-    pub price: i128,
+pub struct Pool {
+    pub balances: Map<Address, i128>, // Holdings by token
+    pub total_shares: i128,     // Includes uncollected fee shares
+    pub fee_shares: i128,       // Fee shares owed to the treasury
+    pub fees_accrued_at: u64,   // Management fee is charged from here on
+    pub high_water_mark: i128,  // Highest NAV per share the performance fee has been charged at
+}
+
+impl Pool {
+    pub fn new(env: &Env) -> Self {
+        Pool {
+            balances: Map::new(env),
+            total_shares: 0,
+            fee_shares: 0,
+            fees_accrued_at: 0,
+            high_water_mark: 0,
+        }
+    }
+
+    pub fn balance(&self, token: &Address) -> i128 {
+        self.balances.get(token.clone()).unwrap_or(0)
+    }
+
+    // Add a (possibly negative) amount to one holding
+    pub fn add(&mut self, token: &Address, amount: i128) {
+        self.balances.set(token.clone(), self.balance(token) + amount);
+    }
+
+    // Holdings backing `shares`, rounded down in favour of the remaining holders
+    pub fn assets_for(&self, shares: i128) -> Result<Map<Address, i128>, Error> {
+        let mut amounts = Map::new(self.balances.env());
+        for (token, balance) in self.balances.iter() {
+            let amount = if self.total_shares == 0 { 0 } else { math::mul_div_down(balance, shares, self.total_shares)? };
+            amounts.set(token, amount);
+        }
+        Ok(amounts)
+    }
+
+    // Burn shares and take the holdings they backed out of the pool
+    pub fn redeem(&mut self, shares: i128) -> Result<Map<Address, i128>, Error> {
+        let amounts = self.assets_for(shares)?;
+        for (token, amount) in amounts.iter() {
+            self.add(&token, -amount);
+        }
+        self.total_shares -= shares;
+        Ok(amounts)
+    }
+}
+
+// Target allocation members can pick; each profile has its own pool
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct RiskProfile {
+    pub name: String,
+    pub targets: Map<Address, u32>, // Target of each asset in basis points; they sum to 10000
+}
+
+impl RiskProfile {
+    pub fn target_bps(&self, token: &Address) -> u32 {
+        self.targets.get(token.clone()).unwrap_or(0)
+    }
+}
+
+// What the fund owes in one asset against what it holds
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct Solvency {
+    pub asset: Address,
+    pub liabilities: i128,
+    pub holdings: i128, // Fund's token balance
+}
+
+impl Solvency {
+    pub fn shortfall(&self) -> i128 {
+        (self.liabilities - self.holdings).max(0)
+    }
+}
+
+// One swap of a rebalance, sized at oracle prices
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct PlannedSwap {
+    pub token_in: Address,
+    pub token_out: Address,
+    pub amount_in: i128,
+    pub expected_out: i128,
+    pub min_out: i128, // Expected output less the slippage tolerance
+}
+
+// Price an asset is valued at and where it came from
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct AssetQuote {
+    pub price: i128,   // Of one whole token, scaled by PRICE_SCALE
+    pub decimals: u32, // Of the token, from the asset registry
+    pub source: PriceSource,
+}
+
+impl AssetQuote {
+    // Value of `amount` base units in USDC base units
+    pub fn value(&self, amount: i128, rounding: Rounding) -> Result<i128, Error> {
+        math::mul_div(amount, self.price, self.value_divisor()?, rounding)
+    }
+
+    // Base units worth `value` USDC base units
+    pub fn amount(&self, value: i128, rounding: Rounding) -> Result<i128, Error> {
+        math::mul_div(value, self.value_divisor()?, self.price, rounding)
+    }
+
+    // Base units of `to` that `amount` base units of this asset buy at the quoted prices
+    pub fn convert(&self, amount: i128, to: &AssetQuote, rounding: Rounding) -> Result<i128, Error> {
+        // Only the difference in decimals is scaled, on whichever side keeps it whole
+        if to.decimals >= self.decimals {
+            let price = math::mul(self.price, math::pow10(to.decimals - self.decimals)?)?;
+            math::mul_div(amount, price, to.price, rounding)
+        } else {
+            let price = math::mul(to.price, math::pow10(self.decimals - to.decimals)?)?;
+            math::mul_div(amount, self.price, price, rounding)
+        }
+    }
+
+    // amount * price / value_divisor is a value in USDC base units
+    fn value_divisor(&self) -> Result<i128, Error> {
+        math::pow10(self.decimals + PRICE_DECIMALS - VALUE_DECIMALS)
+    }
+}
+
+// Pool holdings and their weights at the quoted prices
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct Allocation {
+    pub balances: Map<Address, i128>,
+    pub weights_bps: Map<Address, u32>,
+}
+
+impl Allocation {
+    pub fn balance(&self, token: &Address) -> i128 {
+        self.balances.get(token.clone()).unwrap_or(0)
+    }
+
+    pub fn weight_bps(&self, token: &Address) -> u32 {
+        self.weights_bps.get(token.clone()).unwrap_or(0)
+    }
+}
+
+// What `rebalance` would do to a pool right now
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct RebalancePlan {
+    pub target: RiskProfile,
+    pub quotes: Map<Address, AssetQuote>, // One per asset held or targeted
+    pub swaps: Vec<PlannedSwap>, // Empty while every weight is within the drift band
+    pub current: Allocation,
+    pub resulting: Allocation, // Holdings if every swap fills at its expected output
+}
+
+impl RebalancePlan {
+    pub fn price(&self, token: &Address) -> i128 {
+        self.quotes.get(token.clone()).map(|quote| quote.price).unwrap_or(0)
+    }
+}
+
+// Target-date allocation: members far from retirement hold `from_profile`, and
+// their pool glides linearly to `to_profile` over the last `glide_years`
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct GlidePath {
+    pub from_profile: u32,
+    pub to_profile: u32,
+    pub glide_years: u32,
+}
+
+// Size of each annuity installment
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub enum PayoutAmount {
+    Fixed(i128),     // Fixed amount of the payout asset
+    PercentBps(u32), // Share of the remaining portfolio value (10000 = 100%)
+}
+
+// Decumulation schedule for a retired member
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct PayoutSchedule {
+    pub asset: Address,
+    pub amount: PayoutAmount,
+    pub interval: u64,    // Seconds between installments
+    pub next_payout: u64, // Timestamp the next installment becomes claimable
+}
+
+// Recipient of a share of a member's savings
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct Beneficiary {
+    pub address: Address,
+    pub share_bps: u32, // Shares across all beneficiaries sum to 10000
+}
+
+// Who inherits a member's savings and after how long without activity
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct BeneficiaryPlan {
+    pub beneficiaries: Vec<Beneficiary>,
+    pub inactivity_period: u64, // Seconds without member activity before claims open
+}
+
+// Matching and vesting terms an employer offers its staff
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct EmployerTerms {
+    pub match_bps: u32,      // Match per unit of member deposit value (10000 = 1:1)
+    pub match_cap: i128,     // Max matched value per employee, USDC base units
+    pub vesting_cliff: u64,  // Tenure in seconds before anything vests
+    pub vesting_period: u64, // Tenure in seconds until employer funding is fully vested
+}
+
+// Link between an employee and the employer funding their portfolio
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct Employment {
+    pub employer: Address,
+    pub start: u64,          // First employer contribution; tenure counts from here
+    pub vesting_cliff: u64,  // Terms are fixed when employment starts
+    pub vesting_period: u64,
+    pub matched_value: i128, // Match already paid, USDC base units
+}
+
+// One employee's share of a batch contribution
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct Contribution {
+    pub employee: Address,
+    pub amount: i128,
+}
+
+// Who may receive pool shares through the SEP-41 transfer functions
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[contracttype]
+pub enum TransferPolicy {
+    Disabled,    // Shares are non-transferable (default)
+    MembersOnly, // Only to existing members of the same risk tier
+    Open,        // Also to new addresses, which are enrolled in the sender's tier
+}
+
+// How collected fees are paid to the treasury
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[contracttype]
+pub enum FeeAsset {
+    InKind, // The fee shares' slice of every pool holding
+    Kale,   // KALE, selling other holdings through the router if needed
+}
+
+// Fund fees, taken by minting fee shares into each pool
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct FeeConfig {
+    pub management_fee_bps: u32,  // Annual rate, accrued per second
+    pub performance_fee_bps: u32, // Share of NAV gains above the high-water mark
+    pub treasury: Address,
+    pub asset: FeeAsset,
+}
+
+// Uncollected fees of a pool
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct AccruedFees {
+    pub shares: i128,
+    pub value: i128, // USDC base units at current prices
+}
+
+// Lock-up rules applied to every member
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct RetirementPolicy {
+    pub min_lock_period: u64,   // Minimum seconds between enrollment and retirement date
+    pub early_penalty_bps: u32, // Penalty charged on early withdrawals (10000 = 100%)
+    pub reserve: Address,       // Fund reserve receiving early-withdrawal penalties
+}
+
+// One hour of locally recorded prices for a pair. Each price holds until the
+// next one is seen, so the average weighs prices by time rather than by calls.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct PriceBucket {
+    pub start: u64,          // Start of the hour
+    pub close: i128,         // Last price seen
+    pub updated_at: u64,     // When `close` was seen
+    pub seconds: u64,        // Seconds of the hour priced so far, up to `updated_at`
+    pub price_seconds: i128, // Sum of price times seconds held over those seconds
+}
+
+impl PriceBucket {
+    // Time-weighted average over the priced part of the hour
+    pub fn average(&self) -> i128 {
+        if self.seconds == 0 {
+            return self.close;
+        }
+        self.price_seconds / self.seconds as i128
+    }
+
+    // Hold the close price up to `time`
+    fn hold_until(&mut self, time: u64) {
+        let held = time.saturating_sub(self.updated_at);
+        self.seconds += held;
+        self.price_seconds = self.price_seconds.saturating_add(self.close.saturating_mul(held as i128));
+        self.updated_at = self.updated_at.max(time);
+    }
 }
 
 // Enhanced price cache with freshness tracking
@@ -170,7 +673,7 @@ pub struct CachedPrice {
     pub source: PriceSource,
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 #[contracttype]
 pub enum PriceSource {
     Oracle,
@@ -178,6 +681,14 @@ pub enum PriceSource {
     Cache,
 }
 
+// Where `get_7day_average_price` takes its average from
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub enum AveragingSource {
+    OracleTwap(u32), // Oracle TWAP over this many records; local history only when the oracle can't serve it
+    LocalHistory,    // Prices this contract recorded over the last 7 days
+}
+
 // Oracle health tracking for circuit breaker
 #[derive(Clone)]
 #[contracttype]
@@ -190,16 +701,35 @@ pub struct OracleHealth {
 #[derive(Clone)]
 #[contracttype]
 pub enum DataKey {
+    Admin,
+    PendingAdmin, // Proposed admin awaiting accept_admin
+    RetirementPolicy,
+    PayoutSchedule(Address), // Annuity schedule of a retired member
+    Beneficiaries(Address),  // Inheritance plan of a member
+    Employer(Address),       // Registered employer and its terms
+    Employment(Address),     // Employer link of an employee
     Portfolio(Address),
-    KaleTokenAddress,
-    UsdcTokenAddress,
-    BtcTokenAddress,
+    Pool(u32), // Shared holdings of a risk tier
+    ShareTransferPolicy,
+    FeeConfig,
+    RiskProfiles, // Map of profile id to RiskProfile
+    GlidePath,
+    DriftBand, // Allowed distance of an asset's weight from its target, in bps
+    TargetDatePools, // Ids of target-date pools members have joined
+    Liabilities, // Amount of each asset owed to members across all pools, kept up to date on every pool write
+    Guardian, // May pause operations; only the admin can unpause
+    StorageVersion, // Layout version of stored data; missing on version 1 deployments
+    Paused(Operation),
+    Allowance(Address, Address), // Share allowance from holder to spender
+    Assets, // Map of token address to AssetConfig
+    KaleTokenAddress, // The fund's own token, taken by `deposit` and paid out for `FeeAsset::Kale`
     ReflectorOracleAddress,
     SoroswapRouterAddress,
     MockOracleAddress, // For testing
-    PriceHistory(Symbol), // Store 7-day price history for each pair
+    PriceBuckets(Symbol), // Hourly price history of each pair over the last 7 days
     CachedPrice(Symbol), // Cache recent prices
     OracleHealth,        // Track oracle failures
+    AveragingSource,
 }
 
 #[contract]
@@ -217,19 +747,16 @@ impl KalePensionFund {
         btc_token: Address,
         reflector_oracle: Address,
         soroswap_router: Address,
-    ) {
+    ) -> Result<(), Error> {
         admin.require_auth();
         
-        env.storage().instance().set(&DataKey::KaleTokenAddress, &kale_token);
-        env.storage().instance().set(&DataKey::UsdcTokenAddress, &usdc_token);
-        env.storage().instance().set(&DataKey::BtcTokenAddress, &btc_token);
-        env.storage().instance().set(&DataKey::ReflectorOracleAddress, &reflector_oracle);
-        env.storage().instance().set(&DataKey::SoroswapRouterAddress, &soroswap_router);
+        Self::store_config(&env, &admin, &kale_token, &usdc_token, &btc_token, &reflector_oracle, &soroswap_router)
     }
     
     // Initialize with production addresses (KALE mainnet) - 2024/2025 configuration
-    pub fn initialize_production(env: Env, admin: Address) {
+    pub fn initialize_production(env: Env, admin: Address) -> Result<(), Error> {
         admin.require_auth();
+        Self::ensure_not_initialized(&env)?;
         
         // Real KALE token contract address from kalepail/KALE-sc
         let kale_token = Address::from_string(&String::from_str(&env, "CDL74RF5BLYR2YBLCCI7F5FB6TPSCLKEJUBSD2RSVWZ4YHF3VMFAIGWA"));
@@ -245,11 +772,7 @@ impl KalePensionFund {
         // Soroswap Router mainnet address (updated 2024)
         let soroswap_router = Address::from_string(&String::from_str(&env, "CAG5LRYQ5JVEUI5TEID72EYOVX44TTUJT5BQR2J6J77FH65PCCFAJDDH")); // Soroswap mainnet
         
-        env.storage().instance().set(&DataKey::KaleTokenAddress, &kale_token);
-        env.storage().instance().set(&DataKey::UsdcTokenAddress, &usdc_token);
-        env.storage().instance().set(&DataKey::BtcTokenAddress, &btc_token);
-        env.storage().instance().set(&DataKey::ReflectorOracleAddress, &reflector_oracle);
-        env.storage().instance().set(&DataKey::SoroswapRouterAddress, &soroswap_router);
+        Self::store_config(&env, &admin, &kale_token, &usdc_token, &btc_token, &reflector_oracle, &soroswap_router)?;
         
         // Initialize oracle health tracking
         env.storage().persistent().set(&DataKey::OracleHealth, &OracleHealth {
@@ -257,11 +780,13 @@ impl KalePensionFund {
             last_success_timestamp: env.ledger().timestamp(),
             is_circuit_open: false,
         });
+        Ok(())
     }
     
     // Initialize for testnet with valid addresses - simplified version
-    pub fn initialize_testnet(env: Env, admin: Address) {
+    pub fn initialize_testnet(env: Env, admin: Address) -> Result<(), Error> {
         admin.require_auth();
+        Self::ensure_not_initialized(&env)?;
 
         // Use valid testnet addresses - these are known working Stellar Asset Contract addresses
         // KALE token - using a valid testnet SAC address format
@@ -279,11 +804,7 @@ impl KalePensionFund {
         // Mock router for testnet (we'll use the contract itself as a placeholder)
         let soroswap_router = env.current_contract_address(); // Use contract itself as mock router
 
-        env.storage().instance().set(&DataKey::KaleTokenAddress, &kale_token);
-        env.storage().instance().set(&DataKey::UsdcTokenAddress, &usdc_token);
-        env.storage().instance().set(&DataKey::BtcTokenAddress, &btc_token);
-        env.storage().instance().set(&DataKey::ReflectorOracleAddress, &reflector_oracle);
-        env.storage().instance().set(&DataKey::SoroswapRouterAddress, &soroswap_router);
+        Self::store_config(&env, &admin, &kale_token, &usdc_token, &btc_token, &reflector_oracle, &soroswap_router)?;
 
         // Initialize oracle health tracking
         env.storage().persistent().set(&DataKey::OracleHealth, &OracleHealth {
@@ -293,142 +814,709 @@ impl KalePensionFund {
         });
 
         log!(&env, "✅ Contract initialized for testnet with valid addresses");
+        Ok(())
     }
 
-    // Deposit KALE tokens to the pension fund
-    pub fn deposit(env: Env, user: Address, amount: i128) {
-        user.require_auth();
-        
-        if amount <= 0 {
-            log!(&env, "Error: Amount must be positive");
-            return; // Early return instead of panic
-        }
+    // Propose a new admin; takes effect once the new admin calls accept_admin
+    pub fn transfer_admin(env: Env, new_admin: Address) -> Result<(), Error> {
+        Self::require_admin(&env)?;
+        env.storage().instance().set(&DataKey::PendingAdmin, &new_admin);
+        events::admin_proposed(&env, &new_admin);
+        Ok(())
+    }
 
-        if let Some(kale_token) = env.storage().instance().get::<DataKey, Address>(&DataKey::KaleTokenAddress) {
-            // Transfer KALE tokens from user to contract
-            let token_client = token::Client::new(&env, &kale_token);
-            token_client.transfer(&user, &env.current_contract_address(), &amount);
-        } else {
-            log!(&env, "Error: Contract not initialized - KALE token address missing");
-            return;
+    // Complete a pending admin transfer
+    pub fn accept_admin(env: Env, new_admin: Address) -> Result<(), Error> {
+        new_admin.require_auth();
+
+        let pending: Address = env.storage().instance()
+            .get(&DataKey::PendingAdmin)
+            .ok_or(Error::Unauthorized)?;
+        if pending != new_admin {
+            return Err(Error::Unauthorized);
         }
 
-        // Update or create portfolio
-        let portfolio_key = DataKey::Portfolio(user.clone());
-        let mut portfolio = env.storage().persistent().get(&portfolio_key)
-            .unwrap_or(Portfolio {
-                user: user.clone(),
-                kale_balance: 0,
-                usdc_balance: 0,
-                btc_balance: 0,
-                risk_level: 1, // Default to conservative
-            });
+        env.storage().instance().set(&DataKey::Admin, &new_admin);
+        env.storage().instance().remove(&DataKey::PendingAdmin);
+        events::admin_accepted(&env, &new_admin);
+        Ok(())
+    }
 
-        portfolio.kale_balance += amount;
-        env.storage().persistent().set(&portfolio_key, &portfolio);
+    // Replace the contract code, keeping its address and storage. Run `migrate`
+    // afterwards if the new code uses a newer storage version.
+    pub fn upgrade(env: Env, new_wasm_hash: BytesN<32>) -> Result<(), Error> {
+        Self::require_admin(&env)?;
+
+        env.deployer().update_current_contract_wasm(new_wasm_hash.clone());
+        events::upgraded(&env, new_wasm_hash);
+        Ok(())
     }
 
-    // Set risk profile for user
-    pub fn set_risk(env: Env, user: Address, level: u32) {
-        user.require_auth();
-        
-        if level < 1 || level > 3 {
-            log!(&env, "Error: Risk level must be 1 (conservative), 2 (moderate), or 3 (aggressive)");
-            return; // Early return instead of panic
+    // Convert the fund and the given members' portfolios to the current storage layout.
+    // The fixed KALE, USDC and BTC slots of earlier versions become registry entries, and
+    // version 1 balances are deposited into their risk tier's pool as shares. Members
+    // already converted are skipped, so large funds can migrate in batches. Returns how
+    // many portfolios were converted.
+    pub fn migrate(env: Env, members: Vec<Address>) -> Result<u32, Error> {
+        Self::require_admin(&env)?;
+
+        legacy::migrate_config(&env)?;
+        let mut migrated = 0;
+        for member in members.iter() {
+            if legacy::migrate_member(&env, &member)? {
+                migrated += 1;
+            }
         }
+        // Converted version 1 balances must be backed by tokens the fund actually holds
+        Self::ensure_reconciled(&env)?;
 
-        let portfolio_key = DataKey::Portfolio(user.clone());
-        let mut portfolio: Portfolio = env.storage().persistent().get(&portfolio_key)
-            .expect("Portfolio not found");
+        env.storage().instance().set(&DataKey::StorageVersion, &STORAGE_VERSION);
+        events::migrated(&env, STORAGE_VERSION, migrated);
+        Ok(migrated)
+    }
 
-        portfolio.risk_level = level;
-        env.storage().persistent().set(&portfolio_key, &portfolio);
+    // Storage layout version of this deployment
+    pub fn get_storage_version(env: Env) -> u32 {
+        env.storage().instance().get(&DataKey::StorageVersion).unwrap_or(1)
     }
 
-    // Production-ready price fetching with caching, circuit breaker, and error handling
-    pub fn get_price(env: Env, pair: Symbol) -> i128 {
-        const CACHE_TTL: u32 = 300; // 5 minutes cache
-        const CIRCUIT_FAILURE_THRESHOLD: u32 = 3;
-        const CIRCUIT_RESET_TIME: u64 = 1800; // 30 minutes
-        
-        // 1. Check cache first for performance optimization
-        if let Some(cached_price) = Self::get_cached_price(&env, &pair, CACHE_TTL) {
-            return cached_price.price;
+    // Point price lookups at a different Reflector oracle
+    pub fn set_oracle(env: Env, oracle: Address) -> Result<(), Error> {
+        Self::require_admin(&env)?;
+        env.storage().instance().set(&DataKey::ReflectorOracleAddress, &oracle);
+        events::address_changed(&env, events::ORACLE, &oracle);
+        Ok(())
+    }
+
+    // Point swaps at a different Soroswap router
+    pub fn set_router(env: Env, router: Address) -> Result<(), Error> {
+        Self::require_admin(&env)?;
+        env.storage().instance().set(&DataKey::SoroswapRouterAddress, &router);
+        events::address_changed(&env, events::ROUTER, &router);
+        Ok(())
+    }
+
+    // Register a fund asset, or change an existing one's oracle mapping, decimals or status
+    pub fn set_asset(env: Env, token: Address, config: AssetConfig) -> Result<(), Error> {
+        const MAX_ASSETS: u32 = 10;
+        const MAX_DECIMALS: u32 = 18;
+
+        Self::require_admin(&env)?;
+
+        let mut assets = Self::get_assets(env.clone());
+        let is_new = !assets.contains_key(token.clone());
+        // Prices are looked up by pair, so each pair can value only one asset
+        let pair_taken = assets.iter().any(|(other, asset)| other != token && asset.price_pair == config.price_pair);
+        if config.decimals > MAX_DECIMALS || pair_taken || (is_new && assets.len() >= MAX_ASSETS) {
+            return Err(Error::InvalidConfig);
         }
-        
-        // 2. Check circuit breaker status
-        let mut oracle_health = Self::get_oracle_health(&env);
-        let current_time = env.ledger().timestamp();
-        
-        // Reset circuit if enough time has passed
-        if oracle_health.is_circuit_open && 
-           current_time.saturating_sub(oracle_health.last_success_timestamp) > CIRCUIT_RESET_TIME {
-            oracle_health.is_circuit_open = false;
-            oracle_health.consecutive_failures = 0;
+        // Amounts are valued at the token's own decimals, so they must match what it reports
+        match token::Client::new(&env, &token).try_decimals() {
+            Ok(Ok(decimals)) if decimals == config.decimals => {}
+            Ok(Ok(_)) => return Err(Error::InvalidConfig),
+            _ => return Err(Error::UnsupportedAsset),
         }
-        
-        // 3. Attempt oracle call if circuit is closed
-        let mut price_result: Option<(i128, PriceSource)> = None;
-        
-        if !oracle_health.is_circuit_open {
-            price_result = Self::try_fetch_oracle_price(&env, &pair);
-            
-            match &price_result {
-                Some(_) => {
-                    // Oracle success - reset health tracking
-                    oracle_health.consecutive_failures = 0;
-                    oracle_health.last_success_timestamp = current_time;
-                    oracle_health.is_circuit_open = false;
-                }
-                None => {
-                    // Oracle failure - increment failure count
-                    oracle_health.consecutive_failures += 1;
-                    if oracle_health.consecutive_failures >= CIRCUIT_FAILURE_THRESHOLD {
-                        oracle_health.is_circuit_open = true;
-                    }
-                }
-            }
-            
-            // Save updated health status
-            env.storage().persistent().set(&DataKey::OracleHealth, &oracle_health);
+
+        assets.set(token.clone(), config.clone());
+        env.storage().instance().set(&DataKey::Assets, &assets);
+        events::asset_changed(&env, &token, config);
+        Ok(())
+    }
+
+    // Get every registered asset by token address; assets are disabled, never removed
+    pub fn get_assets(env: Env) -> Map<Address, AssetConfig> {
+        env.storage().instance()
+            .get(&DataKey::Assets)
+            .unwrap_or(Map::new(&env))
+    }
+
+    // Configure the retirement lock-up and early-withdrawal penalty
+    pub fn set_retirement_policy(
+        env: Env,
+        min_lock_period: u64,
+        early_penalty_bps: u32,
+        reserve: Address,
+    ) -> Result<(), Error> {
+        Self::require_admin(&env)?;
+
+        if early_penalty_bps > 10_000 {
+            return Err(Error::InvalidConfig);
         }
-        
-        // 4. Fall back to cached or default prices if oracle fails
-        let (price, source) = price_result.unwrap_or_else(|| {
-            // Try stale cache first (up to 1 hour old)
-            if let Some(stale_cached) = Self::get_cached_price(&env, &pair, 3600) {
-                (stale_cached.price, PriceSource::Cache)
-            } else {
-                // Final fallback to hardcoded prices
-                let fallback_price = Self::get_fallback_price(&env, &pair);
-                (fallback_price, PriceSource::Fallback)
-            }
-        });
-        
-        // 5. Update cache with new price
-        let cached_price = CachedPrice {
-            price,
-            timestamp: current_time,
-            source: source.clone(),
-        };
-        env.storage().persistent().set(&DataKey::CachedPrice(pair.clone()), &cached_price);
-        env.storage().persistent().extend_ttl(&DataKey::CachedPrice(pair.clone()), CACHE_TTL, CACHE_TTL);
-        
-        // 6. Store in price history for TWAP calculations
-        Self::store_price_history(env.clone(), pair.clone(), price);
-        
-        price
+
+        let policy = RetirementPolicy { min_lock_period, early_penalty_bps, reserve };
+        env.storage().instance().set(&DataKey::RetirementPolicy, &policy);
+        events::policy_changed(&env, policy);
+        Ok(())
     }
-    
+
+    // Choose whether members may transfer their pool shares
+    pub fn set_share_transfer_policy(env: Env, policy: TransferPolicy) -> Result<(), Error> {
+        Self::require_admin(&env)?;
+        env.storage().instance().set(&DataKey::ShareTransferPolicy, &policy);
+        events::transfer_policy_changed(&env, policy);
+        Ok(())
+    }
+
+    // Get the share transfer policy (disabled unless the admin opens it)
+    pub fn get_share_transfer_policy(env: Env) -> TransferPolicy {
+        env.storage().instance()
+            .get(&DataKey::ShareTransferPolicy)
+            .unwrap_or(TransferPolicy::Disabled)
+    }
+
+    // Add a risk profile or change an existing one's targets
+    pub fn set_risk_profile(env: Env, profile_id: u32, profile: RiskProfile) -> Result<(), Error> {
+        const MAX_RISK_PROFILES: u32 = 20;
+
+        Self::require_admin(&env)?;
+
+        let assets = Self::get_assets(env.clone());
+        let mut total_bps = 0u64;
+        for (token, bps) in profile.targets.iter() {
+            match assets.get(token) {
+                Some(asset) if asset.enabled => total_bps += bps as u64,
+                _ => return Err(Error::UnsupportedAsset),
+            }
+        }
+        if total_bps != 10_000 || profile_id >= TARGET_DATE_POOLS {
+            return Err(Error::InvalidConfig);
+        }
+        let mut profiles = Self::get_risk_profiles(env.clone());
+        if !profiles.contains_key(profile_id) && profiles.len() >= MAX_RISK_PROFILES {
+            return Err(Error::InvalidConfig);
+        }
+
+        profiles.set(profile_id, profile.clone());
+        env.storage().instance().set(&DataKey::RiskProfiles, &profiles);
+        events::risk_profile_changed(&env, profile_id, profile);
+        Ok(())
+    }
+
+    // Get all risk profiles by id
+    pub fn get_risk_profiles(env: Env) -> Map<u32, RiskProfile> {
+        env.storage().instance()
+            .get(&DataKey::RiskProfiles)
+            .unwrap_or(Map::new(&env))
+    }
+
+    // Configure the glide path followed by target-date pools
+    pub fn set_glide_path(env: Env, from_profile: u32, to_profile: u32, glide_years: u32) -> Result<(), Error> {
+        Self::require_admin(&env)?;
+
+        if glide_years == 0 {
+            return Err(Error::InvalidConfig);
+        }
+        Self::load_risk_profile(&env, from_profile)?;
+        Self::load_risk_profile(&env, to_profile)?;
+
+        let glide_path = GlidePath { from_profile, to_profile, glide_years };
+        env.storage().instance().set(&DataKey::GlidePath, &glide_path);
+        events::glide_path_changed(&env, glide_path);
+        Ok(())
+    }
+
+    // Get the glide path (None until the admin configures one)
+    pub fn get_glide_path(env: Env) -> Option<GlidePath> {
+        env.storage().instance().get(&DataKey::GlidePath)
+    }
+
+    // Allocation a pool currently targets: its risk profile, or for a
+    // target-date pool the point on the glide path its retirement year has reached
+    pub fn get_target_allocation(env: Env, risk_level: u32) -> Result<RiskProfile, Error> {
+        if risk_level < TARGET_DATE_POOLS {
+            return Self::load_risk_profile(&env, risk_level);
+        }
+
+        let glide_path = Self::get_glide_path(env.clone()).ok_or(Error::InvalidConfig)?;
+        let from = Self::load_risk_profile(&env, glide_path.from_profile)?;
+        let to = Self::load_risk_profile(&env, glide_path.to_profile)?;
+
+        let retirement = (risk_level - TARGET_DATE_POOLS) as u64 * SECONDS_PER_YEAR;
+        let window = glide_path.glide_years as u64 * SECONDS_PER_YEAR;
+        let remaining = retirement.saturating_sub(env.ledger().timestamp()).min(window);
+        let mut targets = Map::new(&env);
+        for token in from.targets.keys().iter().chain(to.targets.keys().iter()) {
+            let (from_bps, to_bps) = (from.target_bps(&token) as i128, to.target_bps(&token) as i128);
+            targets.set(token, (to_bps + (from_bps - to_bps) * remaining as i128 / window as i128) as u32);
+        }
+
+        // Rounding dust goes to the asset with the largest target. Interpolation truncates
+        // toward zero, so the targets can add up to slightly more than 10000 as well as less.
+        let dust = 10_000 - targets.values().iter().map(i64::from).sum::<i64>();
+        if let Some((token, bps)) = targets.iter().max_by_key(|(_, bps)| *bps) {
+            targets.set(token, u32::try_from(i64::from(bps) + dust).map_err(|_| Error::InvalidConfig)?);
+        }
+        Ok(RiskProfile {
+            name: String::from_str(&env, "Target date"),
+            targets,
+        })
+    }
+
+    // Set how far an asset's weight may drift from its target before rebalance trades
+    pub fn set_drift_band(env: Env, drift_band_bps: u32) -> Result<(), Error> {
+        Self::require_admin(&env)?;
+
+        if drift_band_bps > 10_000 {
+            return Err(Error::InvalidConfig);
+        }
+        env.storage().instance().set(&DataKey::DriftBand, &drift_band_bps);
+        events::drift_band_changed(&env, drift_band_bps);
+        Ok(())
+    }
+
+    // Get the drift band in basis points (5% unless configured)
+    pub fn get_drift_band(env: Env) -> u32 {
+        const DEFAULT_DRIFT_BAND_BPS: u32 = 500;
+
+        env.storage().instance()
+            .get(&DataKey::DriftBand)
+            .unwrap_or(DEFAULT_DRIFT_BAND_BPS)
+    }
+
+    // Choose where average prices come from
+    pub fn set_averaging_source(env: Env, source: AveragingSource) -> Result<(), Error> {
+        const MAX_TWAP_RECORDS: u32 = 10_000;
+
+        Self::require_admin(&env)?;
+
+        if let AveragingSource::OracleTwap(records) = source {
+            if records == 0 || records > MAX_TWAP_RECORDS {
+                return Err(Error::InvalidConfig);
+            }
+        }
+        env.storage().instance().set(&DataKey::AveragingSource, &source);
+        events::averaging_source_changed(&env, source);
+        Ok(())
+    }
+
+    // Get the averaging source; unless configured, the oracle TWAP over 7 days of 5-minute records
+    pub fn get_averaging_source(env: Env) -> AveragingSource {
+        const DEFAULT_TWAP_RECORDS: u32 = 2_016;
+
+        env.storage().instance()
+            .get(&DataKey::AveragingSource)
+            .unwrap_or(AveragingSource::OracleTwap(DEFAULT_TWAP_RECORDS))
+    }
+
+    // Configure fund fees. Pools accrue under the old terms up to this point.
+    pub fn set_fee_config(
+        env: Env,
+        management_fee_bps: u32,
+        performance_fee_bps: u32,
+        treasury: Address,
+        asset: FeeAsset,
+    ) -> Result<(), Error> {
+        Self::require_admin(&env)?;
+
+        if management_fee_bps > 10_000 || performance_fee_bps > 10_000 {
+            return Err(Error::InvalidConfig);
+        }
+
+        for risk_level in Self::pool_ids(&env) {
+            let pool = Self::load_pool(&env, risk_level)?;
+            Self::save_pool(&env, risk_level, &pool);
+        }
+
+        let config = FeeConfig { management_fee_bps, performance_fee_bps, treasury, asset };
+        env.storage().instance().set(&DataKey::FeeConfig, &config);
+        events::fees_changed(&env, config);
+        Ok(())
+    }
+
+    // Get fee configuration (None while the fund charges nothing)
+    pub fn get_fee_config(env: Env) -> Option<FeeConfig> {
+        env.storage().instance().get(&DataKey::FeeConfig)
+    }
+
+    // Fees a pool owes the treasury, accrued up to now
+    pub fn get_accrued_fees(env: Env, risk_level: u32) -> Result<AccruedFees, Error> {
+        let pool = Self::load_pool(&env, risk_level)?;
+        let value = if pool.total_shares == 0 {
+            0
+        } else {
+            math::mul_div_down(pool.fee_shares, Self::pool_value(&env, &pool)?, pool.total_shares)?
+        };
+        Ok(AccruedFees { shares: pool.fee_shares, value })
+    }
+
+    // Redeem a pool's fee shares and pay them to the treasury. Anyone may call
+    // this; funds only ever go to the treasury. Returns the fee shares redeemed.
+    pub fn collect_fees(env: Env, risk_level: u32) -> Result<i128, Error> {
+        let config = Self::get_fee_config(env.clone()).ok_or(Error::InvalidConfig)?;
+        let mut pool = Self::load_pool(&env, risk_level)?;
+        let shares = pool.fee_shares;
+        if shares == 0 {
+            return Ok(0);
+        }
+
+        let amounts = match config.asset {
+            FeeAsset::InKind => pool.redeem(shares)?,
+            FeeAsset::Kale => {
+                let kale_token = Self::get_address(&env, &DataKey::KaleTokenAddress)?;
+                let kale_quote = Self::live_quote(&env, &kale_token)?;
+                let value = math::mul_div_down(shares, Self::live_pool_value(&env, &pool)?, pool.total_shares)?;
+                let due = kale_quote.amount(value, Rounding::Down)?;
+                if pool.balance(&kale_token) < due {
+                    Self::convert_for_payout(&env, &config.treasury, &mut pool, &kale_token, &kale_quote, due)?;
+                }
+
+                let paid = due.min(pool.balance(&kale_token));
+                pool.add(&kale_token, -paid);
+                pool.total_shares -= shares;
+                Map::from_array(&env, [(kale_token, paid)])
+            }
+        };
+        pool.fee_shares = 0;
+
+        Self::send_all(&env, &config.treasury, &amounts);
+        Self::save_pool(&env, risk_level, &pool);
+
+        events::fees_collected(&env, &config.treasury, risk_level, shares, amounts);
+        Ok(shares)
+    }
+
+    // Liabilities against token holdings for every registered asset
+    pub fn check_solvency(env: Env) -> Vec<Solvency> {
+        let liabilities = Self::get_liabilities(env.clone());
+        let fund = env.current_contract_address();
+        let mut report = Vec::new(&env);
+        for token in Self::get_assets(env.clone()).keys() {
+            report.push_back(Solvency {
+                liabilities: liabilities.get(token.clone()).unwrap_or(0),
+                holdings: token::Client::new(&env, &token).balance(&fund),
+                asset: token,
+            });
+        }
+        report
+    }
+
+    // Get the amount of each asset owed to members, by token
+    pub fn get_liabilities(env: Env) -> Map<Address, i128> {
+        env.storage().instance()
+            .get(&DataKey::Liabilities)
+            .unwrap_or(Map::new(&env))
+    }
+
+    // Pause every operation if any asset is short. Returns whether anything was paused.
+    pub fn pause_if_insolvent(env: Env) -> Result<bool, Error> {
+        Self::require_admin(&env)?;
+
+        let report = Self::check_solvency(env.clone());
+        if !report.iter().any(|solvency| solvency.shortfall() > 0) {
+            return Ok(false);
+        }
+        for operation in Operation::ALL {
+            Self::set_paused(&env, operation, true);
+        }
+        events::paused_on_shortfall(&env, report);
+        Ok(true)
+    }
+
+    // Appoint the guardian who can pause operations in an emergency
+    pub fn set_guardian(env: Env, guardian: Address) -> Result<(), Error> {
+        Self::require_admin(&env)?;
+
+        env.storage().instance().set(&DataKey::Guardian, &guardian);
+        events::address_changed(&env, events::GUARDIAN, &guardian);
+        Ok(())
+    }
+
+    pub fn get_guardian(env: Env) -> Option<Address> {
+        env.storage().instance().get(&DataKey::Guardian)
+    }
+
+    // Halt one operation until the admin lifts the pause
+    pub fn pause(env: Env, operation: Operation) -> Result<(), Error> {
+        let guardian = Self::get_guardian(env.clone()).ok_or(Error::Unauthorized)?;
+        guardian.require_auth();
+
+        Self::set_paused(&env, operation, true);
+        Ok(())
+    }
+
+    // Resume a paused operation
+    pub fn unpause(env: Env, operation: Operation) -> Result<(), Error> {
+        Self::require_admin(&env)?;
+
+        Self::set_paused(&env, operation, false);
+        Ok(())
+    }
+
+    pub fn is_paused(env: Env, operation: Operation) -> bool {
+        env.storage().instance().has(&DataKey::Paused(operation))
+    }
+
+    // Get retirement policy (None until the admin configures one)
+    pub fn get_retirement_policy(env: Env) -> Option<RetirementPolicy> {
+        env.storage().instance().get(&DataKey::RetirementPolicy)
+    }
+
+    // Waive the early-withdrawal penalty for a member facing hardship
+    pub fn approve_hardship(env: Env, user: Address) -> Result<(), Error> {
+        Self::require_admin(&env)?;
+
+        let mut portfolio = Self::load_portfolio(&env, &user)?;
+        portfolio.hardship_approved = true;
+        env.storage().persistent().set(&DataKey::Portfolio(user.clone()), &portfolio);
+
+        events::hardship_approved(&env, &user);
+        Ok(())
+    }
+
+    // Add an employer to the registry so it can contribute for staff
+    pub fn register_employer(env: Env, employer: Address, terms: EmployerTerms) -> Result<(), Error> {
+        Self::require_admin(&env)?;
+        Self::store_employer_terms(&env, &employer, terms)
+    }
+
+    // Remove an employer; existing employer-funded balances keep vesting
+    pub fn remove_employer(env: Env, employer: Address) -> Result<(), Error> {
+        Self::require_admin(&env)?;
+
+        let employer_key = DataKey::Employer(employer.clone());
+        if !env.storage().persistent().has(&employer_key) {
+            return Err(Error::EmployerNotRegistered);
+        }
+        env.storage().persistent().remove(&employer_key);
+
+        events::employer_removed(&env, &employer);
+        Ok(())
+    }
+
+    // Get current admin
+    pub fn get_admin(env: Env) -> Option<Address> {
+        env.storage().instance().get(&DataKey::Admin)
+    }
+
+
+    // Deposit KALE tokens to the pension fund
+    pub fn deposit(env: Env, user: Address, amount: i128) -> Result<(), Error> {
+        Self::ensure_not_paused(&env, Operation::Deposits)?;
+        user.require_auth();
+
+        let kale_token = Self::get_address(&env, &DataKey::KaleTokenAddress)?;
+        Self::deposit_token(&env, &user, &kale_token, amount)
+    }
+
+    // Deposit any enabled fund asset by token address
+    pub fn deposit_asset(env: Env, user: Address, asset: Address, amount: i128) -> Result<(), Error> {
+        Self::ensure_not_paused(&env, Operation::Deposits)?;
+        user.require_auth();
+
+        Self::deposit_token(&env, &user, &asset, amount)
+    }
+
+    // Enroll a new member with the date their savings unlock
+    pub fn enroll(env: Env, user: Address, retirement_date: u64) -> Result<(), Error> {
+        user.require_auth();
+
+        let portfolio_key = DataKey::Portfolio(user.clone());
+        if env.storage().persistent().has(&portfolio_key) {
+            return Err(Error::AlreadyEnrolled);
+        }
+        if retirement_date < Self::earliest_retirement_date(&env) {
+            return Err(Error::InvalidRetirementDate);
+        }
+
+        let mut portfolio = Portfolio::new(user.clone());
+        portfolio.retirement_date = retirement_date;
+        Self::touch(&env, &mut portfolio);
+        env.storage().persistent().set(&portfolio_key, &portfolio);
+
+        events::retirement_date_set(&env, &user, 0, retirement_date);
+        Ok(())
+    }
+
+    // Push the retirement date further out; it can never be brought forward
+    pub fn extend_retirement(env: Env, user: Address, retirement_date: u64) -> Result<(), Error> {
+        user.require_auth();
+
+        let mut portfolio = Self::load_portfolio(&env, &user)?;
+        if retirement_date <= portfolio.retirement_date {
+            return Err(Error::InvalidRetirementDate);
+        }
+
+        let old_date = portfolio.retirement_date;
+        portfolio.retirement_date = retirement_date;
+        // Target-date members follow their new retirement year's pool
+        if portfolio.risk_level >= TARGET_DATE_POOLS {
+            let pool_id = Self::target_date_pool(&env, retirement_date)?;
+            if pool_id != portfolio.risk_level {
+                let old_level = portfolio.risk_level;
+                Self::switch_pool(&env, &mut portfolio, pool_id)?;
+                events::risk_changed(&env, &user, old_level, pool_id);
+            }
+        }
+        Self::touch(&env, &mut portfolio);
+        env.storage().persistent().set(&DataKey::Portfolio(user.clone()), &portfolio);
+
+        events::retirement_date_set(&env, &user, old_date, retirement_date);
+        Ok(())
+    }
+
+    // Move a member to another risk profile (and its pool) by id
+    pub fn set_risk(env: Env, user: Address, level: u32) -> Result<(), Error> {
+        user.require_auth();
+
+        Self::load_risk_profile(&env, level)?;
+
+        let mut portfolio = Self::load_portfolio(&env, &user)?;
+        let old_level = portfolio.risk_level;
+        if level != old_level {
+            Self::switch_pool(&env, &mut portfolio, level)?;
+        }
+        Self::touch(&env, &mut portfolio);
+        env.storage().persistent().set(&DataKey::Portfolio(user.clone()), &portfolio);
+
+        events::risk_changed(&env, &user, old_level, level);
+        Ok(())
+    }
+
+    // Move a member into the target-date pool of their retirement year, whose
+    // allocation de-risks along the glide path as that year approaches
+    pub fn use_target_date(env: Env, user: Address) -> Result<(), Error> {
+        user.require_auth();
+
+        if Self::get_glide_path(env.clone()).is_none() {
+            return Err(Error::InvalidConfig);
+        }
+
+        let mut portfolio = Self::load_portfolio(&env, &user)?;
+        let old_level = portfolio.risk_level;
+        let pool_id = Self::target_date_pool(&env, portfolio.retirement_date)?;
+        if pool_id != old_level {
+            Self::switch_pool(&env, &mut portfolio, pool_id)?;
+        }
+        Self::touch(&env, &mut portfolio);
+        env.storage().persistent().set(&DataKey::Portfolio(user.clone()), &portfolio);
+
+        events::risk_changed(&env, &user, old_level, pool_id);
+        Ok(())
+    }
+
+    // Production-ready price fetching with caching, circuit breaker, and error handling
+    pub fn get_price(env: Env, pair: Symbol) -> Result<i128, Error> {
+        Self::fetch_price(&env, &pair).map(|(price, _)| price)
+    }
+
+    // Price of a pair and where it came from; cached prices keep their original source.
+    // Pairs no registered asset is priced under never reach the oracle, so they cannot
+    // count as oracle failures.
+    fn fetch_price(env: &Env, pair: &Symbol) -> Result<(i128, PriceSource), Error> {
+        const CIRCUIT_FAILURE_THRESHOLD: u32 = 3;
+
+        let asset = Self::symbol_to_reflector_asset(env, pair).ok_or(Error::UnsupportedPair)?;
+
+        // 1. Check cache first for performance optimization
+        if let Some(cached_price) = Self::get_cached_price(env, pair, PRICE_CACHE_TTL) {
+            return Ok((cached_price.price, cached_price.source));
+        }
+        
+        // 2. Check circuit breaker status
+        let mut oracle_health = Self::get_oracle_health(env);
+        let current_time = env.ledger().timestamp();
+        
+        // Reset circuit if enough time has passed
+        if Self::circuit_expired(env, &oracle_health) {
+            events::circuit_closed(env, oracle_health.consecutive_failures);
+            oracle_health.is_circuit_open = false;
+            oracle_health.consecutive_failures = 0;
+        }
+        
+        // 3. Attempt oracle call if circuit is closed
+        let mut price_result: Option<(i128, PriceSource)> = None;
+        
+        if !oracle_health.is_circuit_open {
+            price_result = Self::try_fetch_oracle_price(env, pair, &asset);
+            
+            match &price_result {
+                Some(_) => {
+                    // Oracle success - reset health tracking
+                    oracle_health.consecutive_failures = 0;
+                    oracle_health.last_success_timestamp = current_time;
+                    oracle_health.is_circuit_open = false;
+                }
+                None => {
+                    // Oracle failure - increment failure count
+                    oracle_health.consecutive_failures += 1;
+                    if oracle_health.consecutive_failures >= CIRCUIT_FAILURE_THRESHOLD {
+                        oracle_health.is_circuit_open = true;
+                        events::circuit_opened(env, oracle_health.consecutive_failures);
+                    }
+                }
+            }
+            
+            // Save updated health status
+            env.storage().persistent().set(&DataKey::OracleHealth, &oracle_health);
+        }
+        
+        // 4. Fall back to cached or default prices if oracle fails
+        let (price, source) = match price_result {
+            Some(price_result) => price_result,
+            None => Self::last_resort_price(env, pair)?,
+        };
+        if !matches!(source, PriceSource::Oracle) {
+            events::price_fallback(env, pair, price, source.clone());
+        }
+        
+        // 5. Update cache with new price
+        let cached_price = CachedPrice {
+            price,
+            timestamp: current_time,
+            source: source.clone(),
+        };
+        env.storage().persistent().set(&DataKey::CachedPrice(pair.clone()), &cached_price);
+        env.storage().persistent().extend_ttl(&DataKey::CachedPrice(pair.clone()), PRICE_CACHE_TTL, PRICE_CACHE_TTL);
+        
+        // 6. Store in price history for TWAP calculations
+        Self::store_price_history(env, pair, price);
+        
+        Ok((price, source))
+    }
+
+    // Price of a pair the way `fetch_price` would find it, without writing the cache,
+    // oracle health or price history and without emitting events
+    fn read_price(env: &Env, pair: &Symbol) -> Result<(i128, PriceSource), Error> {
+        let asset = Self::symbol_to_reflector_asset(env, pair).ok_or(Error::UnsupportedPair)?;
+        if let Some(cached_price) = Self::get_cached_price(env, pair, PRICE_CACHE_TTL) {
+            return Ok((cached_price.price, cached_price.source));
+        }
+
+        let oracle_health = Self::get_oracle_health(env);
+        if !oracle_health.is_circuit_open || Self::circuit_expired(env, &oracle_health) {
+            if let Some(price_result) = Self::try_fetch_oracle_price(env, pair, &asset) {
+                return Ok(price_result);
+            }
+        }
+        Self::last_resort_price(env, pair)
+    }
+
+    // Price when the oracle can't serve one: a cached price up to an hour old, else the fallback price.
+    // Pairs without a fallback price are unavailable rather than guessed.
+    fn last_resort_price(env: &Env, pair: &Symbol) -> Result<(i128, PriceSource), Error> {
+        if let Some(stale_cached) = Self::get_cached_price(env, pair, STALE_PRICE_TTL) {
+            return Ok((stale_cached.price, PriceSource::Cache));
+        }
+        Self::get_fallback_price(env, pair)
+            .map(|price| (price, PriceSource::Fallback))
+            .ok_or(Error::OracleUnavailable)
+    }
+
+    // Whether an open circuit breaker has waited long enough for the oracle to be tried again
+    fn circuit_expired(env: &Env, oracle_health: &OracleHealth) -> bool {
+        oracle_health.is_circuit_open
+            && env.ledger().timestamp().saturating_sub(oracle_health.last_success_timestamp) > CIRCUIT_RESET_TIME
+    }
+    
     // Helper function to attempt oracle price fetch
-    fn try_fetch_oracle_price(env: &Env, pair: &Symbol) -> Option<(i128, PriceSource)> {
+    fn try_fetch_oracle_price(env: &Env, pair: &Symbol, asset: &reflector::Asset) -> Option<(i128, PriceSource)> {
         // Try main oracle first
-        if let Some(oracle_result) = Self::try_reflector_oracle(env, pair) {
+        if let Some(oracle_result) = Self::try_reflector_oracle(env, pair, asset) {
             return Some((oracle_result, PriceSource::Oracle));
         }
         
         // Try mock oracle for testing environments
-        if let Some(mock_result) = Self::try_mock_oracle(env, pair) {
+        if let Some(mock_result) = Self::try_mock_oracle(env, asset) {
             return Some((mock_result, PriceSource::Oracle));
         }
         
@@ -436,16 +1524,14 @@ impl KalePensionFund {
     }
     
     // Attempt to fetch from Reflector Oracle - enhanced with better error handling
-    fn try_reflector_oracle(env: &Env, pair: &Symbol) -> Option<i128> {
+    fn try_reflector_oracle(env: &Env, pair: &Symbol, asset: &reflector::Asset) -> Option<i128> {
         let oracle_address: Address = env.storage().instance().get(&DataKey::ReflectorOracleAddress)?;
         let reflector_client = reflector::ReflectorClient::new(env, &oracle_address);
 
-        let asset = Self::symbol_to_reflector_asset(env, pair)?;
-
         // Log the attempt for debugging
         log!(env, "🔍 Attempting to fetch price for {:?} from Reflector oracle", pair);
 
-        match reflector_client.lastprice(&asset) {
+        match reflector_client.lastprice(asset) {
             Some(price_data) => {
                 log!(env, "📊 Reflector returned price: {} at timestamp: {}", price_data.price, price_data.timestamp);
 
@@ -457,7 +1543,8 @@ impl KalePensionFund {
 
                     if age_seconds < 3600 {
                         log!(env, "✅ Using fresh Reflector price for {:?}: {}", pair, price_data.price);
-                        return Some(price_data.price);
+                        let decimals = reflector_client.try_decimals().ok()?.ok()?;
+                        return Self::normalize_price(price_data.price, decimals);
                     } else {
                         log!(env, "⚠️ Reflector price too stale ({} seconds old) for {:?}", age_seconds, pair);
                     }
@@ -474,32 +1561,37 @@ impl KalePensionFund {
     }
     
     // Attempt to fetch from mock oracle (for testing)
-    fn try_mock_oracle(env: &Env, pair: &Symbol) -> Option<i128> {
+    fn try_mock_oracle(env: &Env, asset: &reflector::Asset) -> Option<i128> {
         if let Some(mock_address) = env.storage().instance().get::<DataKey, Address>(&DataKey::MockOracleAddress) {
             let mock_client = MockOracleClient::new(env, &mock_address);
-            let asset = Self::symbol_to_reflector_asset(env, pair)?;
             
-            if let Some(price_data) = mock_client.lastprice(&asset) {
+            if let Some(price_data) = mock_client.lastprice(asset) {
                 if price_data.price > 0 {
-                    return Some(price_data.price);
+                    return Self::normalize_price(price_data.price, mock_client.decimals());
                 }
             }
         }
         None
     }
+
+    // Rescale an oracle price quoted with `decimals` to PRICE_DECIMALS. None if it
+    // doesn't fit or rounds down to nothing.
+    fn normalize_price(price: i128, decimals: u32) -> Option<i128> {
+        let price = if decimals >= PRICE_DECIMALS {
+            price / 10i128.checked_pow(decimals - PRICE_DECIMALS)?
+        } else {
+            price.checked_mul(10i128.checked_pow(PRICE_DECIMALS - decimals)?)?
+        };
+        (price > 0).then_some(price)
+    }
     
-    // Convert symbol to Reflector asset format
+    // Oracle asset of the registered fund asset priced under `pair`
     fn symbol_to_reflector_asset(env: &Env, pair: &Symbol) -> Option<reflector::Asset> {
-        if *pair == Symbol::new(env, "KALE_USD") {
-            let kale_token: Address = env.storage().instance().get(&DataKey::KaleTokenAddress)?;
-            Some(reflector::Asset::Stellar(kale_token))
-        } else if *pair == Symbol::new(env, "BTC_USD") {
-            Some(reflector::Asset::Other(Symbol::new(env, "BTC")))
-        } else if *pair == Symbol::new(env, "USDC_USD") {
-            Some(reflector::Asset::Other(Symbol::new(env, "USDC")))
-        } else {
-            None
-        }
+        Self::get_assets(env.clone())
+            .values()
+            .iter()
+            .find(|asset| asset.price_pair == *pair)
+            .map(|asset| asset.oracle_asset)
     }
     
     // Get cached price if still fresh
@@ -526,84 +1618,132 @@ impl KalePensionFund {
     }
     
     // Fallback prices for critical system operation - updated with current market prices
-    fn get_fallback_price(env: &Env, pair: &Symbol) -> i128 {
+    fn get_fallback_price(env: &Env, pair: &Symbol) -> Option<i128> {
         if *pair == Symbol::new(env, "KALE_USD") || *pair == Symbol::new(env, "KALE_USDC") {
-            120_000_000 // 0.12 USDC per KALE (7 decimals)
+            Some(PRICE_SCALE * 12 / 100) // 0.12 USDC per KALE
         } else if *pair == Symbol::new(env, "BTC_USD") || *pair == Symbol::new(env, "BTC_USDC") {
-            111235_000_000_000 // ~$111,235 (current market price, 7 decimals)
+            Some(PRICE_SCALE * 111_235) // ~$111,235 (current market price)
         } else if *pair == Symbol::new(env, "USDC_USD") {
-            1_000_000_000 // $1.00 (7 decimals)
+            Some(PRICE_SCALE) // $1.00
         } else {
-            // Default fallback for unsupported pairs - return 1:1 ratio
-            1_000_000_000 // $1.00 (7 decimals) - safe fallback instead of panic
+            // Registered assets without a known price have no safe default
+            None
         }
     }
     
-    // Store price point in history for 7-day average calculation
-    fn store_price_history(env: Env, pair: Symbol, price: i128) {
-        let history_key = DataKey::PriceHistory(pair.clone());
-        let mut history: Vec<PricePoint> = env.storage().temporary()
-            .get(&history_key)
-            .unwrap_or(Vec::new(&env));
-            
-        let current_timestamp = env.ledger().timestamp();
-        let new_point = PricePoint {
-            timestamp: current_timestamp,
-            price,
-        };
-        
-        // Add new price point
-        history.push_back(new_point);
-        
-        // Remove price points older than 7 days (604800 seconds)
-        let seven_days_ago = current_timestamp.saturating_sub(604800);
-        while !history.is_empty() {
-            if let Some(first) = history.get(0) {
-                if first.timestamp < seven_days_ago {
-                    history.pop_front();
-                } else {
-                    break;
+    // Record a price in the pair's hourly history, which keeps at most 7 days of buckets
+    fn store_price_history(env: &Env, pair: &Symbol, price: i128) {
+        let history_key = DataKey::PriceBuckets(pair.clone());
+        let mut history = Self::price_buckets(env, pair);
+        let now = env.ledger().timestamp();
+        let start = now - now % HISTORY_BUCKET_SECONDS;
+
+        match history.last() {
+            Some(mut bucket) if bucket.start == start => {
+                bucket.hold_until(now);
+                bucket.close = price;
+                history.set(history.len() - 1, bucket);
+            }
+            previous => {
+                let mut bucket = PriceBucket { start, close: price, updated_at: now, seconds: 0, price_seconds: 0 };
+                // The previous hour's close held until the end of that hour and into this one
+                if let Some(mut last) = previous {
+                    last.hold_until(last.start + HISTORY_BUCKET_SECONDS);
+                    bucket.seconds = now - start;
+                    bucket.price_seconds = last.close.saturating_mul(bucket.seconds as i128);
+                    history.set(history.len() - 1, last);
                 }
-            } else {
-                break;
+                history.push_back(bucket);
             }
         }
-        
+
+        let cutoff = now.saturating_sub(HISTORY_WINDOW_SECONDS);
+        while history.first().is_some_and(|bucket| bucket.start < cutoff) {
+            history.pop_front();
+        }
+
         // Store updated history (TTL extended by 7 days)
         env.storage().temporary().set(&history_key, &history);
-        env.storage().temporary().extend_ttl(&history_key, 604800, 604800);
+        env.storage().temporary().extend_ttl(&history_key, HISTORY_WINDOW_SECONDS as u32, HISTORY_WINDOW_SECONDS as u32);
     }
-    
-    // Calculate 7-day average price
-    pub fn get_7day_average_price(env: Env, pair: Symbol) -> i128 {
-        let history_key = DataKey::PriceHistory(pair.clone());
-        let history: Vec<PricePoint> = env.storage().temporary()
-            .get(&history_key)
-            .unwrap_or(Vec::new(&env));
-
-        if history.is_empty() {
-            // If no history, return current price
-            return Self::get_price(env, pair);
-        }
 
-        // Calculate average of all stored prices
-        let mut total_price = 0i128;
-        let count = history.len();
+    fn price_buckets(env: &Env, pair: &Symbol) -> Vec<PriceBucket> {
+        env.storage().temporary()
+            .get(&DataKey::PriceBuckets(pair.clone()))
+            .unwrap_or(Vec::new(env))
+    }
 
-        for i in 0..count {
-            if let Some(point) = history.get(i) {
-                total_price += point.price;
+    // Hourly buckets of locally recorded prices starting between `from` and `to`, oldest first
+    pub fn get_price_history(env: Env, pair: Symbol, from: u64, to: u64) -> Vec<PriceBucket> {
+        let mut buckets = Vec::new(&env);
+        for bucket in Self::price_buckets(&env, &pair).iter() {
+            if bucket.start >= from && bucket.start <= to {
+                buckets.push_back(bucket);
             }
         }
-
-        total_price / (count as i128)
+        buckets
     }
 
-    // Demo function inspired by the tutorial - simple price comparison
-    // This demonstrates how to use Reflector oracle like in the Euro Guesser tutorial
-    pub fn demo_price_guess(env: Env, asset_symbol: Symbol, will_rise: bool) -> bool {
-        // Get current price from Reflector (like the tutorial's lastprice call)
-        let current_price = Self::get_price(env.clone(), asset_symbol.clone());
+    // Average price of a pair from the configured source
+    pub fn get_7day_average_price(env: Env, pair: Symbol) -> Result<i128, Error> {
+        if let AveragingSource::OracleTwap(records) = Self::get_averaging_source(env.clone()) {
+            if let Some(twap) = Self::try_oracle_twap(&env, &pair, records) {
+                return Ok(twap);
+            }
+        }
+        Self::local_average_price(env, pair)
+    }
+
+    // TWAP of the oracle over its last `records` prices, unless the circuit breaker is open
+    fn try_oracle_twap(env: &Env, pair: &Symbol, records: u32) -> Option<i128> {
+        if Self::get_oracle_health(env).is_circuit_open {
+            return None;
+        }
+        let oracle_address: Address = env.storage().instance().get(&DataKey::ReflectorOracleAddress)?;
+        let reflector_client = reflector::ReflectorClient::new(env, &oracle_address);
+        let asset = Self::symbol_to_reflector_asset(env, pair)?;
+
+        let twap = reflector_client.try_twap(&asset, &records).ok()?.ok()??;
+        let decimals = reflector_client.try_decimals().ok()?.ok()?;
+        Self::normalize_price(twap, decimals)
+    }
+
+    // Time-weighted average of the prices recorded here over the last 7 days
+    fn local_average_price(env: Env, pair: Symbol) -> Result<i128, Error> {
+        let history = Self::price_buckets(&env, &pair);
+        let Some(mut latest) = history.last() else {
+            // If no history, return current price
+            return Self::get_price(env, pair);
+        };
+        // The latest price holds until now
+        latest.hold_until(env.ledger().timestamp());
+
+        let (mut seconds, mut price_seconds) = (0u64, 0i128);
+        let mut previous: Option<PriceBucket> = None;
+        for (index, bucket) in history.iter().enumerate() {
+            let bucket = if index as u32 == history.len() - 1 { latest.clone() } else { bucket };
+            // Hours nobody priced kept the previous close
+            if let Some(previous) = previous {
+                let gap = bucket.start.saturating_sub(previous.start + HISTORY_BUCKET_SECONDS);
+                seconds += gap;
+                price_seconds = price_seconds.saturating_add(previous.close.saturating_mul(gap as i128));
+            }
+            seconds += bucket.seconds;
+            price_seconds = price_seconds.saturating_add(bucket.price_seconds);
+            previous = Some(bucket);
+        }
+
+        if seconds == 0 {
+            return Ok(latest.close);
+        }
+        Ok(price_seconds / seconds as i128)
+    }
+
+    // Demo function inspired by the tutorial - simple price comparison
+    // This demonstrates how to use Reflector oracle like in the Euro Guesser tutorial
+    pub fn demo_price_guess(env: Env, asset_symbol: Symbol, will_rise: bool) -> Result<bool, Error> {
+        // Get current price from Reflector (like the tutorial's lastprice call)
+        let current_price = Self::get_price(env.clone(), asset_symbol.clone())?;
 
         // Simulate waiting 5 minutes by getting a slightly different price
         // In a real scenario, you'd call reflector_client.price(&asset, &timestamp)
@@ -619,637 +1759,3481 @@ impl KalePensionFund {
 
         log!(&env, "📊 Result: Price went up: {}, Guess correct: {}", price_went_up, guess_correct);
 
-        guess_correct
+        Ok(guess_correct)
     }
 
     // Debug function to check contract initialization status
-    pub fn debug_contract_status(env: Env) -> (bool, Option<Address>, Option<Address>, Option<Address>) {
+    pub fn debug_contract_status(env: Env) -> (bool, Option<Address>, Map<Address, AssetConfig>) {
         let kale_token = env.storage().instance().get::<DataKey, Address>(&DataKey::KaleTokenAddress);
-        let usdc_token = env.storage().instance().get::<DataKey, Address>(&DataKey::UsdcTokenAddress);
-        let btc_token = env.storage().instance().get::<DataKey, Address>(&DataKey::BtcTokenAddress);
+        let assets = Self::get_assets(env.clone());
 
-        let is_initialized = kale_token.is_some() && usdc_token.is_some() && btc_token.is_some();
+        let is_initialized = kale_token.is_some() && !assets.is_empty();
 
         log!(&env, "🔍 Contract Debug Status:");
         log!(&env, "  - Initialized: {}", is_initialized);
         if let Some(ref kale) = kale_token {
             log!(&env, "  - KALE Token: {:?}", kale);
         }
-        if let Some(ref usdc) = usdc_token {
-            log!(&env, "  - USDC Token: {:?}", usdc);
+        log!(&env, "  - Registered assets: {}", assets.len());
+
+        (is_initialized, kale_token, assets)
+    }
+
+    // Rebalance the pool of the member's risk tier back to its target allocation
+    // once any asset drifts outside the band. Swaps run once for the whole pool;
+    // every member of the tier shares the result.
+    pub fn rebalance(env: Env, user: Address) -> Result<(), Error> {
+        Self::ensure_not_paused(&env, Operation::Rebalances)?;
+        user.require_auth();
+
+        let mut portfolio = Self::load_portfolio(&env, &user)?;
+        let mut pool = Self::load_pool(&env, portfolio.risk_level)?;
+
+        let plan = Self::plan_rebalance(&env, portfolio.risk_level, &pool, Self::live_quote)?;
+        let before = pool.clone();
+        Self::touch(&env, &mut portfolio);
+
+        if !plan.swaps.is_empty() {
+            let router_address = Self::get_address(&env, &DataKey::SoroswapRouterAddress)?;
+            let router = SoroswapRouterClient::new(&env, &router_address);
+            for swap in plan.swaps.iter() {
+                Self::execute_swap(&env, &user, &router, &mut pool, &swap)?;
+            }
+        }
+
+        Self::save_portfolio(&env, &mut portfolio, &pool)?;
+        Self::ensure_reconciled(&env)?;
+
+        let mut rebalance = events::RebalanceEvent { prices: Map::new(&env), deltas: Map::new(&env) };
+        for (token, quote) in plan.quotes.iter() {
+            rebalance.deltas.set(token.clone(), pool.balance(&token) - before.balance(&token));
+            rebalance.prices.set(token, quote.price);
+        }
+        events::rebalanced(&env, &user, rebalance);
+        Ok(())
+    }
+
+    // Dry run of `rebalance` for the member's pool: the swaps it would make,
+    // at which prices, and the allocation they would leave behind. Writes nothing.
+    pub fn preview_rebalance(env: Env, user: Address) -> Result<RebalancePlan, Error> {
+        let portfolio = Self::stored_portfolio(&env, &user).ok_or(Error::PortfolioNotFound)?;
+        let pool = Self::load_pool(&env, portfolio.risk_level)?;
+        Self::plan_rebalance(&env, portfolio.risk_level, &pool, Self::quote)
+    }
+
+    // Withdraw all assets back to user
+    pub fn withdraw(env: Env, user: Address) -> Result<(), Error> {
+        user.require_auth();
+
+        let mut portfolio = Self::load_portfolio(&env, &user)?;
+        Self::ensure_retired(&env, &portfolio)?;
+        let mut pool = Self::load_pool(&env, portfolio.risk_level)?;
+        Self::forfeit_unvested(&env, &mut portfolio, &mut pool)?;
+
+        // Redeem every share for its slice of the pool
+        let shares = portfolio.shares;
+        let amounts = pool.assets_for(shares)?;
+        Self::pay_out(&env, &mut portfolio, &mut pool, shares, amounts)?;
+
+        // Clear portfolio
+        Self::close_portfolio(&env, &user);
+        Ok(())
+    }
+
+    // Withdraw part of a single asset; the portfolio and its settings stay in place
+    pub fn withdraw_partial(env: Env, user: Address, asset: Address, amount: i128) -> Result<(), Error> {
+        user.require_auth();
+
+        if amount <= 0 {
+            return Err(Error::InvalidAmount);
         }
-        if let Some(ref btc) = btc_token {
-            log!(&env, "  - BTC Token: {:?}", btc);
+
+        Self::load_asset(&env, &asset)?;
+        let mut portfolio = Self::load_portfolio(&env, &user)?;
+        Self::ensure_retired(&env, &portfolio)?;
+        let mut pool = Self::load_pool(&env, portfolio.risk_level)?;
+
+        // Round against the member so even dust withdrawals burn shares
+        let value = Self::live_quote(&env, &asset)?.value(amount, Rounding::Up)?;
+        let shares = Self::shares_to_burn(&pool, Self::live_pool_value(&env, &pool)?, value)?;
+        // Only the member's own slice of the asset, not the rest of the pool's holding
+        if portfolio.balance(&asset) < amount || portfolio.shares < shares {
+            return Err(Error::InsufficientBalance);
         }
 
-        (is_initialized, kale_token, usdc_token, btc_token)
+        Self::touch(&env, &mut portfolio);
+        Self::pay_out(&env, &mut portfolio, &mut pool, shares, Map::from_array(&env, [(asset, amount)]))
     }
 
-    // Rebalance portfolio based on price movement and risk profile
-    pub fn rebalance(env: Env, user: Address) {
+    // Withdraw a USD value (in USDC base units) drawn pro-rata across all assets
+    pub fn withdraw_value(env: Env, user: Address, usd_value: i128) -> Result<(), Error> {
         user.require_auth();
 
-        let portfolio_key = DataKey::Portfolio(user.clone());
-        let mut portfolio: Portfolio = env.storage().persistent().get(&portfolio_key)
-            .expect("Portfolio not found");
+        if usd_value <= 0 {
+            return Err(Error::InvalidAmount);
+        }
 
-        // Get current KALE price
-        let current_price = Self::get_price(env.clone(), Symbol::new(&env, "KALE_USD"));
-        
-        // Get 7-day average price from stored history
-        let avg_price = Self::get_7day_average_price(env.clone(), Symbol::new(&env, "KALE_USD"));
-        
-        let price_change_percent = ((current_price - avg_price) * 100) / avg_price;
+        let mut portfolio = Self::load_portfolio(&env, &user)?;
+        Self::ensure_retired(&env, &portfolio)?;
+        let mut pool = Self::load_pool(&env, portfolio.risk_level)?;
+        let shares = Self::shares_to_burn(&pool, Self::live_pool_value(&env, &pool)?, usd_value)?;
+        if portfolio.shares < shares {
+            return Err(Error::InsufficientBalance);
+        }
 
-        // Determine target allocations based on risk level
-        let (_usdc_target, _btc_target, kale_target) = match portfolio.risk_level {
-            1 => (70, 20, 10), // Conservative
-            2 => (50, 30, 20), // Moderate
-            3 => (30, 40, 30), // Aggressive
-            _ => {
-                log!(&env, "Error: Invalid risk level");
-                return; // Early return instead of panic
-            }
+        // Take the shares' slice of every holding so the pool allocation is unchanged
+        let amounts = pool.assets_for(shares)?;
+        Self::touch(&env, &mut portfolio);
+        Self::pay_out(&env, &mut portfolio, &mut pool, shares, amounts)
+    }
+
+    // Withdraw everything before retirement, paying the early-withdrawal penalty
+    // to the fund reserve unless the admin approved a hardship exception
+    pub fn early_withdraw(env: Env, user: Address) -> Result<(), Error> {
+        user.require_auth();
+
+        let mut portfolio = Self::load_portfolio(&env, &user)?;
+        let is_retired = Self::ensure_retired(&env, &portfolio).is_ok();
+        // Without a policy there is no early exit; the lock-up holds until retirement
+        let policy = match Self::get_retirement_policy(env.clone()) {
+            None if !is_retired => return Err(Error::PolicyNotSet),
+            policy => policy,
+        };
+        let penalty_bps = match &policy {
+            Some(policy) if !is_retired && !portfolio.hardship_approved => policy.early_penalty_bps as i128,
+            _ => 0,
         };
 
-        // Check if rebalancing is needed (price moved >10%)
-        if price_change_percent > 10 {
-            // KALE price spike - sell some KALE
-            let total_value = portfolio.kale_balance * current_price + 
-                            portfolio.usdc_balance * 1_000_000_000 + // USDC is 1:1
-                            portfolio.btc_balance * Self::get_price(env.clone(), Symbol::new(&env, "BTC_USD"));
+        let mut pool = Self::load_pool(&env, portfolio.risk_level)?;
+        if let Some(policy) = policy.filter(|_| penalty_bps > 0) {
+            // The penalty is a share of the member's shares, redeemed to the reserve
+            let penalty_shares = math::mul_div_down(portfolio.shares, penalty_bps, 10_000)?;
+            let penalties = pool.redeem(penalty_shares)?;
+            portfolio.remove_shares(penalty_shares)?;
+            Self::send_all(&env, &policy.reserve, &penalties);
 
-            let target_kale_value = (total_value * kale_target as i128) / 100;
-            let current_kale_value = portfolio.kale_balance * current_price;
-            
-            if current_kale_value > target_kale_value {
-                let excess_kale = (current_kale_value - target_kale_value) / current_price;
-                
-                // Sell half for USDC, half for BTC
-                let kale_to_usdc = excess_kale / 2;
-                let kale_to_btc = excess_kale - kale_to_usdc;
-
-                // Real Soroswap calls for token swaps
-                if let (Some(router_address), Some(kale_token), Some(usdc_token), Some(btc_token)) = (
-                    env.storage().instance().get::<DataKey, Address>(&DataKey::SoroswapRouterAddress),
-                    env.storage().instance().get::<DataKey, Address>(&DataKey::KaleTokenAddress),
-                    env.storage().instance().get::<DataKey, Address>(&DataKey::UsdcTokenAddress),
-                    env.storage().instance().get::<DataKey, Address>(&DataKey::BtcTokenAddress)
-                ) {
-                    let router = SoroswapRouterClient::new(&env, &router_address);
-                
-                let deadline = env.ledger().timestamp() + 300; // 5 minutes from now
-                
-                // Swap KALE for USDC
-                if kale_to_usdc > 0 {
-                    let mut path_kale_usdc = Vec::new(&env);
-                    path_kale_usdc.push_back(kale_token.clone());
-                    path_kale_usdc.push_back(usdc_token.clone());
-                    
-                    let expected_usdc = kale_to_usdc * current_price / 1_000_000_000;
-                    let min_usdc = expected_usdc * 95 / 100; // 5% slippage tolerance
-                    
-                    match router.try_swap_exact_tokens_for_tokens(
-                        kale_to_usdc,
-                        min_usdc,
-                        path_kale_usdc,
-                        env.current_contract_address(),
-                        deadline
-                    ) {
-                        Ok(amounts) => {
-                            portfolio.kale_balance -= kale_to_usdc;
-                            if let Some(usdc_received) = amounts.get(1) {
-                                portfolio.usdc_balance += usdc_received;
-                            }
-                        },
-                        Err(_) => {
-                            // Fallback to mock calculation if swap fails
-                            portfolio.kale_balance -= kale_to_usdc;
-                            portfolio.usdc_balance += expected_usdc;
-                        }
-                    }
-                }
-                
-                // Swap KALE for BTC
-                if kale_to_btc > 0 {
-                    let mut path_kale_btc = Vec::new(&env);
-                    path_kale_btc.push_back(kale_token.clone());
-                    path_kale_btc.push_back(btc_token.clone());
-                    
-                    let btc_price = Self::get_price(env.clone(), Symbol::new(&env, "BTC_USD"));
-                    let expected_btc = kale_to_btc * current_price / btc_price;
-                    let min_btc = expected_btc * 95 / 100; // 5% slippage tolerance
-                    
-                    match router.try_swap_exact_tokens_for_tokens(
-                        kale_to_btc,
-                        min_btc,
-                        path_kale_btc,
-                        env.current_contract_address(),
-                        deadline
-                    ) {
-                        Ok(amounts) => {
-                            portfolio.kale_balance -= kale_to_btc;
-                            if let Some(btc_received) = amounts.get(1) {
-                                portfolio.btc_balance += btc_received;
-                            }
-                        },
-                        Err(_) => {
-                            // Fallback to mock calculation if swap fails
-                            portfolio.kale_balance -= kale_to_btc;
-                            portfolio.btc_balance += expected_btc;
-                        }
-                    }
-                }
-                } else {
-                    log!(&env, "Error: Contract not properly initialized - missing token addresses");
-                    return;
-                }
+            events::early_penalty(&env, &user, &policy.reserve, penalties);
+        }
+
+        Self::forfeit_unvested(&env, &mut portfolio, &mut pool)?;
+        let shares = portfolio.shares;
+        let remaining = pool.assets_for(shares)?;
+        Self::pay_out(&env, &mut portfolio, &mut pool, shares, remaining)?;
+
+        // Early exit closes the portfolio, like a full withdrawal
+        Self::close_portfolio(&env, &user);
+        Ok(())
+    }
+
+    // Switch a retired member to periodic payouts; the first installment is due immediately
+    pub fn set_payout_schedule(
+        env: Env,
+        user: Address,
+        asset: Address,
+        amount: PayoutAmount,
+        interval: u64,
+    ) -> Result<(), Error> {
+        user.require_auth();
+
+        Self::load_asset(&env, &asset)?;
+        let mut portfolio = Self::load_portfolio(&env, &user)?;
+        Self::ensure_retired(&env, &portfolio)?;
+
+        let valid_amount = match amount {
+            PayoutAmount::Fixed(value) => value > 0,
+            PayoutAmount::PercentBps(bps) => bps > 0 && bps <= 10_000,
+        };
+        if !valid_amount || interval == 0 {
+            return Err(Error::InvalidConfig);
+        }
+
+        let schedule = PayoutSchedule {
+            asset,
+            amount,
+            interval,
+            next_payout: env.ledger().timestamp(),
+        };
+        env.storage().persistent().set(&DataKey::PayoutSchedule(user.clone()), &schedule);
+
+        Self::touch(&env, &mut portfolio);
+        env.storage().persistent().set(&DataKey::Portfolio(user.clone()), &portfolio);
+
+        events::payout_scheduled(&env, &user, schedule);
+        Ok(())
+    }
+
+    // Stop periodic payouts; the balance stays invested
+    pub fn cancel_payout_schedule(env: Env, user: Address) -> Result<(), Error> {
+        user.require_auth();
+
+        let schedule_key = DataKey::PayoutSchedule(user.clone());
+        if !env.storage().persistent().has(&schedule_key) {
+            return Err(Error::NoPayoutSchedule);
+        }
+        env.storage().persistent().remove(&schedule_key);
+
+        events::payout_cancelled(&env, &user);
+        Ok(())
+    }
+
+    // Release accrued annuity installments to the member. Anyone may call this;
+    // funds only ever go to the member. Returns the amount of payout asset sent.
+    pub fn claim_payout(env: Env, user: Address) -> Result<i128, Error> {
+        const MAX_INSTALLMENTS_PER_CLAIM: u64 = 24;
+
+        let mut portfolio = Self::load_portfolio(&env, &user)?;
+        Self::ensure_retired(&env, &portfolio)?;
+        let schedule_key = DataKey::PayoutSchedule(user.clone());
+        let mut schedule: PayoutSchedule = env.storage().persistent()
+            .get(&schedule_key)
+            .ok_or(Error::NoPayoutSchedule)?;
+
+        let now = env.ledger().timestamp();
+        if now < schedule.next_payout {
+            return Err(Error::PayoutNotDue);
+        }
+        let accrued = (now - schedule.next_payout) / schedule.interval + 1;
+        let installments = accrued.min(MAX_INSTALLMENTS_PER_CLAIM);
+
+        let quote = Self::live_quote(&env, &schedule.asset)?;
+        let mut pool = Self::load_pool(&env, portfolio.risk_level)?;
+        let total_value = Self::portfolio_value(&env, &portfolio, &pool)?;
+
+        // Amount due in payout asset units, never more than the whole portfolio
+        let due_value = match schedule.amount {
+            PayoutAmount::Fixed(amount) => {
+                quote.value(math::mul(amount, installments as i128)?, Rounding::Down)?.min(total_value)
             }
-        } else if price_change_percent < -10 {
-            // KALE price dip - buy KALE with USDC/BTC
-            let total_value = portfolio.kale_balance * current_price + 
-                            portfolio.usdc_balance * 1_000_000_000 +
-                            portfolio.btc_balance * Self::get_price(env.clone(), Symbol::new(&env, "BTC_USD"));
-
-            let target_kale_value = (total_value * kale_target as i128) / 100;
-            let current_kale_value = portfolio.kale_balance * current_price;
-            
-            if current_kale_value < target_kale_value {
-                let needed_kale_value = target_kale_value - current_kale_value;
-                
-                // Buy KALE with available USDC and BTC
-                let usdc_to_spend = portfolio.usdc_balance.min(needed_kale_value / 2 / 1_000_000_000);
-                let btc_to_spend = portfolio.btc_balance.min(needed_kale_value / 2 / Self::get_price(env.clone(), Symbol::new(&env, "BTC_USD")));
-
-                // Real Soroswap calls for buying KALE
-                if let (Some(router_address), Some(kale_token), Some(usdc_token), Some(btc_token)) = (
-                    env.storage().instance().get::<DataKey, Address>(&DataKey::SoroswapRouterAddress),
-                    env.storage().instance().get::<DataKey, Address>(&DataKey::KaleTokenAddress),
-                    env.storage().instance().get::<DataKey, Address>(&DataKey::UsdcTokenAddress),
-                    env.storage().instance().get::<DataKey, Address>(&DataKey::BtcTokenAddress)
-                ) {
-                    let router = SoroswapRouterClient::new(&env, &router_address);
-                
-                let deadline = env.ledger().timestamp() + 300; // 5 minutes from now
-                
-                // Swap USDC for KALE
-                if usdc_to_spend > 0 {
-                    let mut path_usdc_kale = Vec::new(&env);
-                    path_usdc_kale.push_back(usdc_token.clone());
-                    path_usdc_kale.push_back(kale_token.clone());
-                    
-                    let expected_kale = usdc_to_spend * 1_000_000_000 / current_price;
-                    let min_kale = expected_kale * 95 / 100; // 5% slippage tolerance
-                    
-                    match router.try_swap_exact_tokens_for_tokens(
-                        usdc_to_spend,
-                        min_kale,
-                        path_usdc_kale,
-                        env.current_contract_address(),
-                        deadline
-                    ) {
-                        Ok(amounts) => {
-                            portfolio.usdc_balance -= usdc_to_spend;
-                            if let Some(kale_received) = amounts.get(1) {
-                                portfolio.kale_balance += kale_received;
-                            }
-                        },
-                        Err(_) => {
-                            // Fallback to mock calculation if swap fails
-                            portfolio.usdc_balance -= usdc_to_spend;
-                            portfolio.kale_balance += expected_kale;
-                        }
-                    }
-                }
-                
-                // Swap BTC for KALE
-                if btc_to_spend > 0 {
-                    let mut path_btc_kale = Vec::new(&env);
-                    path_btc_kale.push_back(btc_token.clone());
-                    path_btc_kale.push_back(kale_token.clone());
-                    
-                    let btc_price = Self::get_price(env.clone(), Symbol::new(&env, "BTC_USD"));
-                    let expected_kale = btc_to_spend * btc_price / current_price;
-                    let min_kale = expected_kale * 95 / 100; // 5% slippage tolerance
-                    
-                    match router.try_swap_exact_tokens_for_tokens(
-                        btc_to_spend,
-                        min_kale,
-                        path_btc_kale,
-                        env.current_contract_address(),
-                        deadline
-                    ) {
-                        Ok(amounts) => {
-                            portfolio.btc_balance -= btc_to_spend;
-                            if let Some(kale_received) = amounts.get(1) {
-                                portfolio.kale_balance += kale_received;
-                            }
-                        },
-                        Err(_) => {
-                            // Fallback to mock calculation if swap fails
-                            portfolio.btc_balance -= btc_to_spend;
-                            portfolio.kale_balance += expected_kale;
-                        }
-                    }
+            PayoutAmount::PercentBps(bps) => {
+                let mut remaining = total_value;
+                for _ in 0..installments {
+                    remaining -= math::mul_div_down(remaining, bps as i128, 10_000)?;
                 }
+                total_value - remaining
+            }
+        };
+        let due = quote.amount(due_value, Rounding::Down)?;
+
+        // Top up the payout asset from the pool's other holdings when it runs short.
+        // Shares are burned for everything the payout takes out of the pool before the
+        // swaps, so the conversion's slippage stays with the member being paid.
+        let pool_value = Self::live_pool_value(&env, &pool)?;
+        let mut cost = 0;
+        if pool.balance(&schedule.asset) < due {
+            cost = Self::convert_for_payout(&env, &user, &mut pool, &schedule.asset, &quote, due)?;
+        }
+        let paid = due.min(pool.balance(&schedule.asset));
+        let taken_value = math::add(quote.value(paid, Rounding::Up)?, cost)?;
+        let shares = Self::shares_to_burn(&pool, pool_value, taken_value)?
+            .min(portfolio.shares);
+
+        schedule.next_payout += installments * schedule.interval;
+        env.storage().persistent().set(&schedule_key, &schedule);
+
+        let amounts = Map::from_array(&env, [(schedule.asset.clone(), paid)]);
+        Self::pay_out(&env, &mut portfolio, &mut pool, shares, amounts)?;
+
+        events::payout(&env, &user, &schedule.asset, paid, installments as u32);
+        Ok(paid)
+    }
+
+    // Name who inherits the member's savings once they stop interacting with the fund
+    // for `inactivity_period` seconds. An empty list removes the plan.
+    pub fn set_beneficiaries(
+        env: Env,
+        user: Address,
+        beneficiaries: Vec<Beneficiary>,
+        inactivity_period: u64,
+    ) -> Result<(), Error> {
+        const MAX_BENEFICIARIES: u32 = 10;
+        const MIN_INACTIVITY_PERIOD: u64 = 180 * 86_400; // ~6 months
+
+        user.require_auth();
+
+        let mut portfolio = Self::load_portfolio(&env, &user)?;
+        Self::touch(&env, &mut portfolio);
+        env.storage().persistent().set(&DataKey::Portfolio(user.clone()), &portfolio);
+
+        let plan_key = DataKey::Beneficiaries(user.clone());
+        if beneficiaries.is_empty() {
+            env.storage().persistent().remove(&plan_key);
+            events::beneficiaries_changed(&env, &user, Vec::new(&env), 0);
+            return Ok(());
+        }
+
+        if beneficiaries.len() > MAX_BENEFICIARIES || inactivity_period < MIN_INACTIVITY_PERIOD {
+            return Err(Error::InvalidBeneficiaries);
+        }
+        let mut total_bps = 0u32;
+        for beneficiary in beneficiaries.iter() {
+            if beneficiary.share_bps == 0 || beneficiary.address == user {
+                return Err(Error::InvalidBeneficiaries);
+            }
+            total_bps += beneficiary.share_bps;
+        }
+        if total_bps != 10_000 {
+            return Err(Error::InvalidBeneficiaries);
+        }
+
+        let plan = BeneficiaryPlan { beneficiaries: beneficiaries.clone(), inactivity_period };
+        env.storage().persistent().set(&plan_key, &plan);
+
+        events::beneficiaries_changed(&env, &user, beneficiaries, inactivity_period);
+        Ok(())
+    }
+
+    // Refresh the inactivity timer without changing anything else
+    pub fn check_in(env: Env, user: Address) -> Result<(), Error> {
+        user.require_auth();
+
+        let mut portfolio = Self::load_portfolio(&env, &user)?;
+        Self::touch(&env, &mut portfolio);
+        env.storage().persistent().set(&DataKey::Portfolio(user), &portfolio);
+        Ok(())
+    }
+
+    // Distribute an inactive member's balances to all beneficiaries according to
+    // their shares. Any listed beneficiary may trigger it; lock-ups do not apply.
+    pub fn claim_inheritance(env: Env, beneficiary: Address, member: Address) -> Result<(), Error> {
+        beneficiary.require_auth();
+
+        let mut portfolio = Self::load_portfolio(&env, &member)?;
+        let plan: BeneficiaryPlan = env.storage().persistent()
+            .get(&DataKey::Beneficiaries(member.clone()))
+            .ok_or(Error::NoBeneficiaries)?;
+
+        if !plan.beneficiaries.iter().any(|b| b.address == beneficiary) {
+            return Err(Error::NotBeneficiary);
+        }
+        let expires_at = portfolio.last_activity.saturating_add(plan.inactivity_period);
+        if env.ledger().timestamp() < expires_at {
+            return Err(Error::MemberStillActive);
+        }
+
+        let mut pool = Self::load_pool(&env, portfolio.risk_level)?;
+        Self::forfeit_unvested(&env, &mut portfolio, &mut pool)?;
+        let holdings = pool.redeem(portfolio.shares)?;
+        Self::save_pool(&env, portfolio.risk_level, &pool);
+
+        // Split each holding by share; the last beneficiary also receives rounding dust
+        let mut remaining = holdings.clone();
+        let last_index = plan.beneficiaries.len() - 1;
+        for (index, heir) in plan.beneficiaries.iter().enumerate() {
+            let mut amounts = Map::new(&env);
+            for (token, holding) in holdings.iter() {
+                let left = remaining.get_unchecked(token.clone());
+                let amount = if index as u32 == last_index {
+                    left
                 } else {
-                    log!(&env, "Error: Contract not properly initialized - missing token addresses for KALE purchase");
-                    return;
+                    math::mul_div_down(holding, heir.share_bps as i128, 10_000)?
+                };
+                if amount > 0 {
+                    remaining.set(token.clone(), left - amount);
+                    amounts.set(token, amount);
                 }
             }
+
+            Self::send_all(&env, &heir.address, &amounts);
+            events::inheritance(&env, &member, &heir.address, amounts);
         }
 
-        env.storage().persistent().set(&portfolio_key, &portfolio);
+        Self::close_portfolio(&env, &member);
+        Ok(())
     }
 
-    // Withdraw all assets back to user
-    pub fn withdraw(env: Env, user: Address) {
-        user.require_auth();
+    // Let a registered employer update its matching and vesting terms.
+    // Vesting changes only apply to employees who join afterwards.
+    pub fn set_employer_terms(env: Env, employer: Address, terms: EmployerTerms) -> Result<(), Error> {
+        employer.require_auth();
 
-        let portfolio_key = DataKey::Portfolio(user.clone());
-        let Some(portfolio) = env.storage().persistent().get::<DataKey, Portfolio>(&portfolio_key) else {
-            log!(&env, "Error: Portfolio not found");
+        if !env.storage().persistent().has(&DataKey::Employer(employer.clone())) {
+            return Err(Error::EmployerNotRegistered);
+        }
+        Self::store_employer_terms(&env, &employer, terms)
+    }
+
+    // Contribute on behalf of one employee, topping up any employer match owed
+    pub fn contribute_for(
+        env: Env,
+        employer: Address,
+        employee: Address,
+        asset: Address,
+        amount: i128,
+    ) -> Result<(), Error> {
+        Self::ensure_not_paused(&env, Operation::Deposits)?;
+        employer.require_auth();
+
+        Self::load_enabled_asset(&env, &asset)?;
+        let terms = Self::load_employer(&env, &employer)?;
+        Self::contribute(&env, &employer, &terms, &employee, &asset, amount)
+    }
+
+    // Contribute for many employees in one transaction
+    pub fn contribute_batch(
+        env: Env,
+        employer: Address,
+        asset: Address,
+        contributions: Vec<Contribution>,
+    ) -> Result<(), Error> {
+        Self::ensure_not_paused(&env, Operation::Deposits)?;
+        employer.require_auth();
+
+        Self::load_enabled_asset(&env, &asset)?;
+        let terms = Self::load_employer(&env, &employer)?;
+        for contribution in contributions.iter() {
+            Self::contribute(&env, &employer, &terms, &contribution.employee, &asset, contribution.amount)?;
+        }
+        Ok(())
+    }
+
+    // Get a registered employer's terms
+    pub fn get_employer(env: Env, employer: Address) -> Option<EmployerTerms> {
+        env.storage().persistent().get(&DataKey::Employer(employer))
+    }
+
+    // Get the employer link of an employee, if any
+    pub fn get_employment(env: Env, employee: Address) -> Option<Employment> {
+        env.storage().persistent().get(&DataKey::Employment(employee))
+    }
+
+    // Get a member's inheritance plan, if any
+    pub fn get_beneficiaries(env: Env, user: Address) -> Option<BeneficiaryPlan> {
+        env.storage().persistent().get(&DataKey::Beneficiaries(user))
+    }
+
+    // Get a member's annuity schedule, if any
+    pub fn get_payout_schedule(env: Env, user: Address) -> Option<PayoutSchedule> {
+        env.storage().persistent().get(&DataKey::PayoutSchedule(user))
+    }
+
+    // Get the shared holdings of a risk tier
+    pub fn get_pool(env: Env, risk_level: u32) -> Result<Pool, Error> {
+        Self::load_pool(&env, risk_level)
+    }
+
+    // NAV per share of a risk tier's pool, scaled like prices (1_000_000_000 == 1 USDC base unit)
+    pub fn get_share_price(env: Env, risk_level: u32) -> Result<i128, Error> {
+        let pool = Self::load_pool(&env, risk_level)?;
+        if pool.total_shares == 0 {
+            return Ok(PRICE_SCALE);
+        }
+        math::mul_div_down(Self::pool_value(&env, &pool)?, PRICE_SCALE, pool.total_shares)
+    }
+
+    // Get user portfolio
+    pub fn get_portfolio(env: Env, user: Address) -> Portfolio {
+        Self::load_portfolio(&env, &user).unwrap_or(Portfolio::new(user))
+    }
+
+    // Transfer tokens in from the user and credit the matching portfolio balance
+    fn deposit_token(env: &Env, user: &Address, token: &Address, amount: i128) -> Result<(), Error> {
+        Self::load_enabled_asset(env, token)?;
+        if amount <= 0 {
+            return Err(Error::InvalidAmount);
+        }
+
+        let token_client = token::Client::new(env, token);
+        token_client.transfer(user, &env.current_contract_address(), &amount);
+
+        // Update or create portfolio and mint pool shares at the current NAV
+        let mut portfolio = Self::load_or_enroll(env, user);
+        let mut pool = Self::load_pool(env, portfolio.risk_level)?;
+        portfolio.shares += Self::mint_shares(env, &mut pool, Map::from_array(env, [(token.clone(), amount)]))?;
+        portfolio.own_contributions += Self::asset_value(env, token, amount)?;
+        Self::touch(env, &mut portfolio);
+        Self::save_portfolio(env, &mut portfolio, &pool)?;
+
+        events::deposit(env, user, token, amount);
+        Ok(())
+    }
+
+    // Credit an employer contribution plus any match owed against the employee's own deposits
+    fn contribute(
+        env: &Env,
+        employer: &Address,
+        terms: &EmployerTerms,
+        employee: &Address,
+        token: &Address,
+        amount: i128,
+    ) -> Result<(), Error> {
+        if amount <= 0 {
+            return Err(Error::InvalidAmount);
+        }
+
+        let employment_key = DataKey::Employment(employee.clone());
+        let mut employment = match env.storage().persistent().get::<DataKey, Employment>(&employment_key) {
+            Some(employment) if employment.employer != *employer => return Err(Error::EmployedElsewhere),
+            Some(employment) => employment,
+            None => Employment {
+                employer: employer.clone(),
+                start: env.ledger().timestamp(),
+                vesting_cliff: terms.vesting_cliff,
+                vesting_period: terms.vesting_period,
+                matched_value: 0,
+            },
+        };
+        let mut portfolio = Self::load_or_enroll(env, employee);
+        let mut pool = Self::load_pool(env, portfolio.risk_level)?;
+
+        // Match is owed on the member's own deposits, up to the employer's cap
+        let match_target = math::mul_div_down(portfolio.own_contributions, terms.match_bps as i128, 10_000)?.min(terms.match_cap);
+        let match_value = (match_target - employment.matched_value).max(0);
+        let match_amount = Self::live_quote(env, token)?.amount(match_value, Rounding::Down)?;
+        let total = amount + match_amount;
+
+        token::Client::new(env, token).transfer(employer, &env.current_contract_address(), &total);
+
+        // The already-vested share of new funding is immediately the member's
+        let shares = Self::mint_shares(env, &mut pool, Map::from_array(env, [(token.clone(), total)]))?;
+        let vested = math::mul_div_down(shares, portfolio.vested_bps as i128, 10_000)?;
+        portfolio.shares += vested;
+        portfolio.employer_shares += shares - vested;
+        employment.matched_value += match_value;
+
+        Self::save_portfolio(env, &mut portfolio, &pool)?;
+        env.storage().persistent().set(&employment_key, &employment);
+
+        events::contribution(env, employer, employee, token, amount, match_amount);
+        Ok(())
+    }
+
+    // Validate and store an employer's terms
+    fn store_employer_terms(env: &Env, employer: &Address, terms: EmployerTerms) -> Result<(), Error> {
+        if terms.match_bps > 10_000 || terms.match_cap < 0 || terms.vesting_cliff > terms.vesting_period {
+            return Err(Error::InvalidConfig);
+        }
+
+        env.storage().persistent().set(&DataKey::Employer(employer.clone()), &terms);
+        events::employer_terms(env, employer, terms);
+        Ok(())
+    }
+
+    fn load_employer(env: &Env, employer: &Address) -> Result<EmployerTerms, Error> {
+        env.storage().persistent()
+            .get(&DataKey::Employer(employer.clone()))
+            .ok_or(Error::EmployerNotRegistered)
+    }
+
+    // Move newly vested employer-funded shares to the member
+    fn apply_vesting(env: &Env, portfolio: &mut Portfolio) {
+        let Some((shares, vested_bps)) = Self::newly_vested(env, portfolio) else {
             return;
         };
+        portfolio.employer_shares -= shares;
+        portfolio.shares += shares;
+        portfolio.vested_bps = vested_bps;
 
-        if let (Some(kale_token), Some(usdc_token), Some(btc_token)) = (
-            env.storage().instance().get::<DataKey, Address>(&DataKey::KaleTokenAddress),
-            env.storage().instance().get::<DataKey, Address>(&DataKey::UsdcTokenAddress),
-            env.storage().instance().get::<DataKey, Address>(&DataKey::BtcTokenAddress)
-        ) {
+        events::vested(env, &portfolio.user, shares);
+    }
 
-        // Transfer all tokens back to user
-        if portfolio.kale_balance > 0 {
-            let kale_client = token::Client::new(&env, &kale_token);
-            kale_client.transfer(&env.current_contract_address(), &user, &portfolio.kale_balance);
+    // Employer-funded shares vested since the portfolio was last updated, and the
+    // vested share of employer funding they bring it to
+    fn newly_vested(env: &Env, portfolio: &Portfolio) -> Option<(i128, u32)> {
+        if portfolio.vested_bps >= 10_000 || portfolio.employer_shares == 0 {
+            return None;
         }
-        
-        if portfolio.usdc_balance > 0 {
-            let usdc_client = token::Client::new(&env, &usdc_token);
-            usdc_client.transfer(&env.current_contract_address(), &user, &portfolio.usdc_balance);
+        let employment = env.storage().persistent()
+            .get::<DataKey, Employment>(&DataKey::Employment(portfolio.user.clone()))?;
+
+        let tenure = env.ledger().timestamp().saturating_sub(employment.start);
+        let target_bps = if tenure < employment.vesting_cliff {
+            0
+        } else if tenure >= employment.vesting_period {
+            10_000
+        } else {
+            (tenure as u128 * 10_000 / employment.vesting_period as u128) as u32
+        };
+        if target_bps <= portfolio.vested_bps {
+            return None;
         }
-        
-        if portfolio.btc_balance > 0 {
-            let btc_client = token::Client::new(&env, &btc_token);
-            btc_client.transfer(&env.current_contract_address(), &user, &portfolio.btc_balance);
+
+        // Unvested shares cover (10000 - vested_bps); release the newly vested slice of them
+        let shares = portfolio.employer_shares * (target_bps - portfolio.vested_bps) as i128
+            / (10_000 - portfolio.vested_bps) as i128;
+        Some((shares, target_bps))
+    }
+
+    // Return unvested employer funding to the employer when a member exits
+    fn forfeit_unvested(env: &Env, portfolio: &mut Portfolio, pool: &mut Pool) -> Result<(), Error> {
+        if portfolio.employer_shares == 0 {
+            return Ok(());
+        }
+        let Some(employment) = env.storage().persistent()
+            .get::<DataKey, Employment>(&DataKey::Employment(portfolio.user.clone())) else {
+            return Ok(());
+        };
+
+        let forfeited = pool.redeem(portfolio.employer_shares)?;
+        portfolio.employer_shares = 0;
+        Self::send_all(env, &employment.employer, &forfeited);
+
+        events::forfeited(env, &portfolio.user, &employment.employer, forfeited);
+        Ok(())
+    }
+
+    // Burn member shares and send amounts by token out of the pool to the owner
+    fn pay_out(
+        env: &Env,
+        portfolio: &mut Portfolio,
+        pool: &mut Pool,
+        shares: i128,
+        amounts: Map<Address, i128>,
+    ) -> Result<(), Error> {
+        portfolio.remove_shares(shares)?;
+        pool.total_shares -= shares;
+        for (token, amount) in amounts.iter() {
+            if amount > 0 {
+                pool.add(&token, -amount);
+            }
+        }
+        Self::send_all(env, &portfolio.user, &amounts);
+
+        Self::save_portfolio(env, portfolio, pool)?;
+
+        events::withdraw(env, &portfolio.user, amounts);
+        Ok(())
+    }
+
+    // Transfer every positive amount by token out of the contract
+    fn send_all(env: &Env, to: &Address, amounts: &Map<Address, i128>) {
+        for (token, amount) in amounts.iter() {
+            if amount > 0 {
+                token::Client::new(env, &token).transfer(&env.current_contract_address(), to, &amount);
+            }
+        }
+    }
+
+    // Drop a member's portfolio together with its payout and inheritance settings. The
+    // employment stays, so leaving and rejoining cannot reset the match already paid.
+    fn close_portfolio(env: &Env, user: &Address) {
+        env.storage().persistent().remove(&DataKey::Portfolio(user.clone()));
+        env.storage().persistent().remove(&DataKey::PayoutSchedule(user.clone()));
+        env.storage().persistent().remove(&DataKey::Beneficiaries(user.clone()));
+    }
+
+    // Record a member action, restarting the inheritance inactivity timer
+    fn touch(env: &Env, portfolio: &mut Portfolio) {
+        portfolio.last_activity = env.ledger().timestamp();
+    }
+
+    // Sell other pool holdings through the router until `target` can cover `due`, at
+    // live prices. Returns the value the sales took out of the pool beyond what they
+    // brought in, which is the slippage the payout has to answer for.
+    fn convert_for_payout(
+        env: &Env,
+        user: &Address,
+        pool: &mut Pool,
+        target: &Address,
+        target_quote: &AssetQuote,
+        due: i128,
+    ) -> Result<i128, Error> {
+        let router_address = Self::get_address(env, &DataKey::SoroswapRouterAddress)?;
+        let router = SoroswapRouterClient::new(env, &router_address);
+        let mut cost = 0;
+
+        for token in pool.balances.keys() {
+            let shortfall = due - pool.balance(target);
+            if token == *target || shortfall <= 0 {
+                continue;
+            }
+
+            let quote = Self::live_quote(env, &token)?;
+
+            // Round up so the sale covers the shortfall before slippage
+            let needed = target_quote.convert(shortfall, &quote, Rounding::Up)?;
+            let amount_in = pool.balance(&token).min(needed);
+            if amount_in <= 0 {
+                continue;
+            }
+
+            let expected_out = quote.convert(amount_in, target_quote, Rounding::Down)?;
+            let min_out = math::mul_div_down(expected_out, 95, 100)?; // 5% slippage tolerance
+            let (spent, received) = Self::swap_tokens(env, &router, &token, target, amount_in, min_out)?
+                .ok_or(Error::SwapFailed)?;
+
+            pool.add(&token, -spent);
+            pool.add(target, received);
+            cost += quote.value(spent, Rounding::Up)? - target_quote.value(received, Rounding::Down)?;
+
+            events::swap(env, user, events::SwapEvent {
+                token_in: token,
+                token_out: target.clone(),
+                amount_in: spent,
+                amount_out: received,
+                min_out,
+            });
+        }
+        Ok(cost)
+    }
+
+    // Plan the swaps that bring a pool back to its target allocation, or none while
+    // every asset's weight is within the drift band. Each overweight asset is sold straight
+    // into the underweight ones, so n assets never need more than n - 1 swaps.
+    fn plan_rebalance(
+        env: &Env,
+        risk_level: u32,
+        pool: &Pool,
+        quote: fn(&Env, &Address) -> Result<AssetQuote, Error>,
+    ) -> Result<RebalancePlan, Error> {
+        const SLIPPAGE_BPS: i128 = 500; // 5% slippage tolerance
+
+        // Target allocation of the member's risk profile or glide path
+        let target = Self::get_target_allocation(env.clone(), risk_level)?;
+
+        // Quote every asset the pool holds or the target asks for
+        let mut quotes = Map::new(env);
+        for token in pool.balances.keys().iter().chain(target.targets.keys().iter()) {
+            if !quotes.contains_key(token.clone()) {
+                quotes.set(token.clone(), quote(env, &token)?);
+            }
         }
 
-            // Clear portfolio
-            env.storage().persistent().remove(&portfolio_key);
-        } else {
-            log!(&env, "Error: Contract not properly initialized - missing token addresses for withdrawal");
-            return;
-        }
+        let current = Self::allocation(pool, &quotes)?;
+        let mut plan = RebalancePlan {
+            target,
+            quotes,
+            swaps: Vec::new(env),
+            current: current.clone(),
+            resulting: current,
+        };
+
+        let values = Self::holding_values(pool, &plan.quotes)?;
+        let total = values.values().iter().try_fold(0, math::add)?;
+        if total <= 0 {
+            return Ok(plan);
+        }
+
+        // Value each asset must gain (positive) or shed (negative) to sit on target
+        let drift_band = Self::get_drift_band(env.clone()) as i128;
+        let mut deltas = Map::new(env);
+        let mut drifted = false;
+        for (token, value) in values.iter() {
+            let target_bps = plan.target.target_bps(&token) as i128;
+            let weight_bps = math::mul_div_down(value, 10_000, total)?;
+            drifted |= (weight_bps - target_bps).abs() > drift_band;
+            deltas.set(token, math::mul_div_down(total, target_bps, 10_000)? - value);
+        }
+        if !drifted {
+            return Ok(plan);
+        }
+
+        let mut after = pool.clone();
+        let tokens = plan.quotes.keys();
+        for token_in in tokens.iter() {
+            for token_out in tokens.iter() {
+                let (excess, need) = (-deltas.get_unchecked(token_in.clone()), deltas.get_unchecked(token_out.clone()));
+                if excess <= 0 || need <= 0 {
+                    continue;
+                }
+                let value = excess.min(need);
+                deltas.set(token_in.clone(), value - excess);
+                deltas.set(token_out.clone(), need - value);
+
+                let (quote_in, quote_out) = (plan.quotes.get_unchecked(token_in.clone()), plan.quotes.get_unchecked(token_out.clone()));
+                let amount_in = quote_in.amount(value, Rounding::Down)?.min(pool.balance(&token_in));
+                let expected_out = quote_in.convert(amount_in, &quote_out, Rounding::Down)?;
+                if amount_in > 0 && expected_out > 0 {
+                    after.add(&token_in, -amount_in);
+                    after.add(&token_out, expected_out);
+                    plan.swaps.push_back(PlannedSwap {
+                        token_in: token_in.clone(),
+                        token_out,
+                        amount_in,
+                        expected_out,
+                        min_out: math::mul_div_down(expected_out, 10_000 - SLIPPAGE_BPS, 10_000)?,
+                    });
+                }
+            }
+        }
+        plan.resulting = Self::allocation(&after, &plan.quotes)?;
+        Ok(plan)
+    }
+
+    // Value of each priced holding in USDC base units
+    fn holding_values(pool: &Pool, quotes: &Map<Address, AssetQuote>) -> Result<Map<Address, i128>, Error> {
+        let mut values = Map::new(quotes.env());
+        for (token, quote) in quotes.iter() {
+            values.set(token.clone(), quote.value(pool.balance(&token), Rounding::Down)?);
+        }
+        Ok(values)
+    }
+
+    // Holdings of a pool with each asset's weight of the total value
+    fn allocation(pool: &Pool, quotes: &Map<Address, AssetQuote>) -> Result<Allocation, Error> {
+        let values = Self::holding_values(pool, quotes)?;
+        let total = values.values().iter().try_fold(0, math::add)?;
+        let mut weights_bps = Map::new(quotes.env());
+        for (token, value) in values.iter() {
+            weights_bps.set(token, if total <= 0 { 0 } else { math::mul_div_down(value, 10_000, total)? as u32 });
+        }
+
+        Ok(Allocation { balances: pool.balances.clone(), weights_bps })
+    }
+
+    // Run one planned swap through the router for a pool. A swap the router rejects
+    // is skipped with an event and leaves the pool untouched.
+    fn execute_swap(
+        env: &Env,
+        user: &Address,
+        router: &SoroswapRouterClient,
+        pool: &mut Pool,
+        swap: &PlannedSwap,
+    ) -> Result<(), Error> {
+        let mut event = events::SwapEvent {
+            token_in: swap.token_in.clone(),
+            token_out: swap.token_out.clone(),
+            amount_in: swap.amount_in,
+            amount_out: 0,
+            min_out: swap.min_out,
+        };
+
+        match Self::swap_tokens(env, router, &event.token_in, &event.token_out, swap.amount_in, swap.min_out)? {
+            Some((spent, received)) => {
+                pool.add(&swap.token_in, -spent);
+                pool.add(&swap.token_out, received);
+                event.amount_in = spent;
+                event.amount_out = received;
+                events::swap(env, user, event);
+            }
+            None => events::swap_failed(env, user, event),
+        }
+        Ok(())
+    }
+
+    // Swap through the router and return the amounts that actually left and reached
+    // the fund, read from its token balances rather than the router's reply. None if
+    // the router rejected the swap, which rolls it back. A router that takes more than
+    // asked or pays out less than the minimum fails the whole call.
+    fn swap_tokens(
+        env: &Env,
+        router: &SoroswapRouterClient,
+        token_in: &Address,
+        token_out: &Address,
+        amount_in: i128,
+        min_out: i128,
+    ) -> Result<Option<(i128, i128)>, Error> {
+        Self::ensure_not_paused(env, Operation::Swaps)?;
+        let fund = env.current_contract_address();
+        let (input, output) = (token::Client::new(env, token_in), token::Client::new(env, token_out));
+        let (input_before, output_before) = (input.balance(&fund), output.balance(&fund));
+
+        let mut path = Vec::new(env);
+        path.push_back(token_in.clone());
+        path.push_back(token_out.clone());
+        let deadline = env.ledger().timestamp() + 300; // 5 minutes from now
+
+        // The router moves the input from the fund to the pair in a nested call,
+        // which the fund has to authorize up front
+        let Ok(pair) = router.try_router_pair_for(token_in, token_out) else {
+            return Ok(None);
+        };
+        env.authorize_as_current_contract(Vec::from_array(env, [InvokerContractAuthEntry::Contract(SubContractInvocation {
+            context: ContractContext {
+                contract: token_in.clone(),
+                fn_name: Symbol::new(env, "transfer"),
+                args: (fund.clone(), pair, amount_in).into_val(env),
+            },
+            sub_invocations: Vec::new(env),
+        })]));
+        if router.try_swap_exact_tokens_for_tokens(amount_in, min_out, path, fund.clone(), deadline).is_err() {
+            return Ok(None);
+        }
+
+        let spent = input_before - input.balance(&fund);
+        let received = output.balance(&fund) - output_before;
+        if spent > amount_in || received < min_out {
+            return Err(Error::SwapFailed);
+        }
+        Ok(Some((spent, received)))
+    }
+
+    // Pools may never claim more of an asset than the fund's token balance holds
+    fn ensure_reconciled(env: &Env) -> Result<(), Error> {
+        if Self::check_solvency(env.clone()).iter().any(|solvency| solvency.shortfall() > 0) {
+            return Err(Error::BalanceMismatch);
+        }
+        Ok(())
+    }
+
+    // Current quote of a registered asset, from the oracle or else the cache or fallback price.
+    // Quoting writes nothing, so previews and views stay read-only.
+    fn quote(env: &Env, token: &Address) -> Result<AssetQuote, Error> {
+        let asset = Self::load_asset(env, token)?;
+        let (price, source) = Self::read_price(env, &asset.price_pair)?;
+        if price <= 0 {
+            return Err(Error::OracleUnavailable);
+        }
+        Ok(AssetQuote { price, decimals: asset.decimals, source })
+    }
+
+    // Quote shares may be minted or burned at: a stale cached or fallback price
+    // would let members trade against the rest of their pool
+    fn live_quote(env: &Env, token: &Address) -> Result<AssetQuote, Error> {
+        let quote = Self::quote(env, token)?;
+        if quote.source != PriceSource::Oracle {
+            return Err(Error::OracleUnavailable);
+        }
+        Ok(quote)
+    }
+
+    // Value of an amount of one asset in USDC base units
+    fn asset_value(env: &Env, token: &Address, amount: i128) -> Result<i128, Error> {
+        Self::quote(env, token)?.value(amount, Rounding::Down)
+    }
+
+    // Total pool value in USDC base units at current prices
+    fn pool_value(env: &Env, pool: &Pool) -> Result<i128, Error> {
+        Self::total_value(env, &pool.balances, Self::quote)
+    }
+
+    // Total pool value at live oracle prices, for minting and burning shares
+    fn live_pool_value(env: &Env, pool: &Pool) -> Result<i128, Error> {
+        Self::total_value(env, &pool.balances, Self::live_quote)
+    }
+
+    // Value of amounts by token in USDC base units at the prices `quote` gives
+    fn total_value(
+        env: &Env,
+        amounts: &Map<Address, i128>,
+        quote: fn(&Env, &Address) -> Result<AssetQuote, Error>,
+    ) -> Result<i128, Error> {
+        let mut total = 0i128;
+        for (token, amount) in amounts.iter() {
+            if amount != 0 {
+                total = math::add(total, quote(env, &token)?.value(amount, Rounding::Down)?)?;
+            }
+        }
+        Ok(total)
+    }
+
+    // Value of a member's shares in USDC base units at live oracle prices
+    fn portfolio_value(env: &Env, portfolio: &Portfolio, pool: &Pool) -> Result<i128, Error> {
+        if pool.total_shares == 0 {
+            return Ok(0);
+        }
+        math::mul_div_down(portfolio.shares, Self::live_pool_value(env, pool)?, pool.total_shares)
+    }
+
+    // Add assets to a pool and mint shares for their value at the pre-deposit NAV,
+    // both at live oracle prices. The first deposit into an empty pool mints one
+    // share per USDC base unit.
+    fn mint_shares(env: &Env, pool: &mut Pool, amounts: Map<Address, i128>) -> Result<i128, Error> {
+        let pool_value = Self::live_pool_value(env, pool)?;
+        let value = Self::total_value(env, &amounts, Self::live_quote)?;
+        for (token, amount) in amounts.iter() {
+            if amount != 0 {
+                pool.add(&token, amount);
+            }
+        }
+
+        let shares = if pool.total_shares == 0 || pool_value <= 0 {
+            value
+        } else {
+            math::mul_div_down(value, pool.total_shares, pool_value)?
+        };
+        pool.total_shares = math::add(pool.total_shares, shares)?;
+        Ok(shares)
+    }
+
+    // Shares to burn for a USD value leaving the pool, rounded up in favour of the remaining holders
+    fn shares_to_burn(pool: &Pool, pool_value: i128, value: i128) -> Result<i128, Error> {
+        if pool_value <= 0 {
+            return Ok(pool.total_shares);
+        }
+        math::mul_div_up(value, pool.total_shares, pool_value)
+    }
+
+    // Move a member's holdings in kind from their current pool into another tier's pool
+    fn switch_pool(env: &Env, portfolio: &mut Portfolio, risk_level: u32) -> Result<(), Error> {
+        let mut old_pool = Self::load_pool(env, portfolio.risk_level)?;
+        let mut new_pool = Self::load_pool(env, risk_level)?;
+
+        let member_assets = old_pool.redeem(portfolio.shares)?;
+        let employer_assets = old_pool.redeem(portfolio.employer_shares)?;
+        portfolio.shares = Self::mint_shares(env, &mut new_pool, member_assets)?;
+        portfolio.employer_shares = Self::mint_shares(env, &mut new_pool, employer_assets)?;
+
+        Self::save_pool(env, portfolio.risk_level, &old_pool);
+        portfolio.risk_level = risk_level;
+        Self::save_portfolio(env, portfolio, &new_pool)
+    }
+
+    // Target-date pool for a retirement date, registering it on first use
+    fn target_date_pool(env: &Env, retirement_date: u64) -> Result<u32, Error> {
+        let pool_id = u32::try_from(retirement_date / SECONDS_PER_YEAR)
+            .ok()
+            .and_then(|year| TARGET_DATE_POOLS.checked_add(year))
+            .ok_or(Error::InvalidRetirementDate)?;
+        let mut pools: Vec<u32> = env.storage().instance()
+            .get(&DataKey::TargetDatePools)
+            .unwrap_or(Vec::new(env));
+        if !pools.contains(pool_id) {
+            pools.push_back(pool_id);
+            env.storage().instance().set(&DataKey::TargetDatePools, &pools);
+        }
+        Ok(pool_id)
+    }
+
+    // Every pool that can hold assets: one per risk profile and each target-date pool in use
+    fn pool_ids(env: &Env) -> Vec<u32> {
+        let mut pool_ids = Self::get_risk_profiles(env.clone()).keys();
+        let target_date_pools: Vec<u32> = env.storage().instance()
+            .get(&DataKey::TargetDatePools)
+            .unwrap_or(Vec::new(env));
+        pool_ids.append(&target_date_pools);
+        pool_ids
+    }
+
+    // Load a risk tier's pool with fees accrued up to now
+    fn load_pool(env: &Env, risk_level: u32) -> Result<Pool, Error> {
+        let mut pool = env.storage().persistent()
+            .get(&DataKey::Pool(risk_level))
+            .unwrap_or_else(|| Pool::new(env));
+        Self::accrue_fees(env, &mut pool)?;
+        Ok(pool)
+    }
+
+    // Persist a pool and move the fund-wide liabilities by its balance changes
+    fn save_pool(env: &Env, risk_level: u32, pool: &Pool) {
+        let key = DataKey::Pool(risk_level);
+        let old = env.storage().persistent().get(&key).unwrap_or_else(|| Pool::new(env));
+        let mut liabilities = Self::get_liabilities(env.clone());
+        // Pools never drop a token once held, so the new holdings cover every old one
+        for (token, balance) in pool.balances.iter() {
+            let change = balance - old.balance(&token);
+            if change != 0 {
+                liabilities.set(token.clone(), liabilities.get(token).unwrap_or(0) + change);
+            }
+        }
+        env.storage().instance().set(&DataKey::Liabilities, &liabilities);
+        env.storage().persistent().set(&key, pool);
+    }
+
+    // Mint fee shares for the management fee since the last accrual and for
+    // the performance fee on any NAV gain above the pool's high-water mark
+    fn accrue_fees(env: &Env, pool: &mut Pool) -> Result<(), Error> {
+        let now = env.ledger().timestamp();
+        let config = match Self::get_fee_config(env.clone()) {
+            Some(config) if pool.total_shares > 0 => config,
+            _ => {
+                // Empty pools restart at one share per USDC base unit
+                if pool.total_shares == 0 {
+                    pool.high_water_mark = PRICE_SCALE;
+                }
+                pool.fees_accrued_at = now;
+                return Ok(());
+            }
+        };
+
+        let elapsed = now.saturating_sub(pool.fees_accrued_at) as i128;
+        if elapsed > 0 && config.management_fee_bps > 0 {
+            let fee_bps_seconds = math::mul(config.management_fee_bps as i128, elapsed)?;
+            let shares = math::mul_div_down(pool.total_shares, fee_bps_seconds, 10_000 * SECONDS_PER_YEAR as i128)?;
+            pool.total_shares += shares;
+            pool.fee_shares += shares;
+        }
+        pool.fees_accrued_at = now;
+
+        if config.performance_fee_bps > 0 {
+            // Gains are only measured at live prices; without them the performance fee
+            // waits, so loading a pool never depends on a price being available
+            let value = match Self::live_pool_value(env, pool) {
+                Err(Error::OracleUnavailable) => return Ok(()),
+                value => value?,
+            };
+            let nav = math::mul_div_down(value, PRICE_SCALE, pool.total_shares)?;
+            if pool.high_water_mark == 0 {
+                pool.high_water_mark = nav;
+            } else if nav > pool.high_water_mark {
+                // Dilute holders by exactly the fee's share of the gain
+                let gain = math::mul_div_down(nav - pool.high_water_mark, pool.total_shares, PRICE_SCALE)?;
+                let fee_value = math::mul_div_down(gain, config.performance_fee_bps as i128, 10_000)?;
+                let shares = math::mul_div_down(fee_value, pool.total_shares, value - fee_value)?;
+                pool.total_shares += shares;
+                pool.fee_shares += shares;
+                pool.high_water_mark = math::mul_div_down(value, PRICE_SCALE, pool.total_shares)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Persist a member's portfolio together with their pool, refreshing the derived balances
+    fn save_portfolio(env: &Env, portfolio: &mut Portfolio, pool: &Pool) -> Result<(), Error> {
+        Self::sync_balances(portfolio, pool)?;
+        Self::save_pool(env, portfolio.risk_level, pool);
+        env.storage().persistent().set(&DataKey::Portfolio(portfolio.user.clone()), portfolio);
+        Ok(())
+    }
+
+    // Recompute a member's per-asset view from their shares
+    fn sync_balances(portfolio: &mut Portfolio, pool: &Pool) -> Result<(), Error> {
+        portfolio.balances = pool.assets_for(portfolio.shares)?;
+        Ok(())
+    }
+
+    // Registry entry of a fund asset
+    fn load_asset(env: &Env, token: &Address) -> Result<AssetConfig, Error> {
+        Self::get_assets(env.clone())
+            .get(token.clone())
+            .ok_or(Error::UnsupportedAsset)
+    }
+
+    // Registry entry of an asset that still takes deposits
+    fn load_enabled_asset(env: &Env, token: &Address) -> Result<AssetConfig, Error> {
+        match Self::load_asset(env, token)? {
+            asset if asset.enabled => Ok(asset),
+            _ => Err(Error::UnsupportedAsset),
+        }
+    }
+
+    // Earliest retirement date a member enrolling now may choose
+    fn earliest_retirement_date(env: &Env) -> u64 {
+        let min_lock_period = Self::get_retirement_policy(env.clone())
+            .map(|policy| policy.min_lock_period)
+            .unwrap_or(0);
+        env.ledger().timestamp().saturating_add(min_lock_period)
+    }
+
+    // Normal withdrawals are only open once the member's retirement date has passed
+    fn ensure_retired(env: &Env, portfolio: &Portfolio) -> Result<(), Error> {
+        if env.ledger().timestamp() < portfolio.retirement_date {
+            return Err(Error::RetirementLocked);
+        }
+        Ok(())
+    }
+
+    // Load a stored portfolio or fail if the user never deposited.
+    // Vested employer shares are released and the per-asset view refreshed on the way.
+    fn load_portfolio(env: &Env, user: &Address) -> Result<Portfolio, Error> {
+        let mut portfolio = Self::stored_portfolio(env, user).ok_or(Error::PortfolioNotFound)?;
+        Self::apply_vesting(env, &mut portfolio);
+        let pool = Self::load_pool(env, portfolio.risk_level)?;
+        Self::sync_balances(&mut portfolio, &pool)?;
+        Ok(portfolio)
+    }
+
+    // Portfolio exactly as stored, without releasing vested shares
+    fn stored_portfolio(env: &Env, user: &Address) -> Option<Portfolio> {
+        env.storage().persistent().get(&DataKey::Portfolio(user.clone()))
+    }
+
+    // Load a portfolio, enrolling the user with the earliest allowed retirement date if new
+    fn load_or_enroll(env: &Env, user: &Address) -> Portfolio {
+        Self::load_portfolio(env, user).unwrap_or_else(|_| {
+            let mut portfolio = Portfolio::new(user.clone());
+            portfolio.retirement_date = Self::earliest_retirement_date(env);
+            events::retirement_date_set(env, user, 0, portfolio.retirement_date);
+            portfolio
+        })
+    }
+
+    // Persist the admin and external contract addresses; only allowed once
+    fn store_config(
+        env: &Env,
+        admin: &Address,
+        kale_token: &Address,
+        usdc_token: &Address,
+        btc_token: &Address,
+        reflector_oracle: &Address,
+        soroswap_router: &Address,
+    ) -> Result<(), Error> {
+        Self::ensure_not_initialized(env)?;
+
+        env.storage().instance().set(&DataKey::Admin, admin);
+        env.storage().instance().set(&DataKey::KaleTokenAddress, kale_token);
+        env.storage().instance().set(&DataKey::Assets, &Self::default_assets(env, kale_token, usdc_token, btc_token)?);
+        env.storage().instance().set(&DataKey::ReflectorOracleAddress, reflector_oracle);
+        env.storage().instance().set(&DataKey::SoroswapRouterAddress, soroswap_router);
+        let profiles = Self::default_risk_profiles(env, kale_token, usdc_token, btc_token);
+        env.storage().instance().set(&DataKey::RiskProfiles, &profiles);
+        env.storage().instance().set(&DataKey::StorageVersion, &STORAGE_VERSION);
+
+        events::initialized(env, admin, events::ConfigEvent {
+            kale_token: kale_token.clone(),
+            usdc_token: usdc_token.clone(),
+            btc_token: btc_token.clone(),
+            oracle: reflector_oracle.clone(),
+            router: soroswap_router.clone(),
+        });
+        Ok(())
+    }
+
+    // KALE, USDC and BTC, the assets every fund starts with. Decimals are read from each
+    // token, which like `set_asset` rejects a token that can't report them.
+    fn default_assets(env: &Env, kale_token: &Address, usdc_token: &Address, btc_token: &Address) -> Result<Map<Address, AssetConfig>, Error> {
+        let asset = |token: &Address, pair: &str, oracle_asset: reflector::Asset| match token::Client::new(env, token).try_decimals() {
+            Ok(Ok(decimals)) => Ok(AssetConfig {
+                price_pair: Symbol::new(env, pair),
+                oracle_asset,
+                decimals,
+                enabled: true,
+            }),
+            _ => Err(Error::UnsupportedAsset),
+        };
+
+        let mut assets = Map::new(env);
+        assets.set(kale_token.clone(), asset(kale_token, "KALE_USD", reflector::Asset::Stellar(kale_token.clone()))?);
+        assets.set(usdc_token.clone(), asset(usdc_token, "USDC_USD", reflector::Asset::Other(Symbol::new(env, "USDC")))?);
+        assets.set(btc_token.clone(), asset(btc_token, "BTC_USD", reflector::Asset::Other(Symbol::new(env, "BTC")))?);
+        Ok(assets)
+    }
+
+    // Conservative, moderate and aggressive profiles every fund starts with
+    fn default_risk_profiles(env: &Env, kale_token: &Address, usdc_token: &Address, btc_token: &Address) -> Map<u32, RiskProfile> {
+        let profile = |name: &str, kale_bps: u32, usdc_bps: u32, btc_bps: u32| RiskProfile {
+            name: String::from_str(env, name),
+            targets: Map::from_array(env, [
+                (kale_token.clone(), kale_bps),
+                (usdc_token.clone(), usdc_bps),
+                (btc_token.clone(), btc_bps),
+            ]),
+        };
+
+        let mut profiles = Map::new(env);
+        profiles.set(1, profile("Conservative", 1_000, 7_000, 2_000));
+        profiles.set(2, profile("Moderate", 2_000, 5_000, 3_000));
+        profiles.set(3, profile("Aggressive", 3_000, 3_000, 4_000));
+        profiles
+    }
+
+    fn load_risk_profile(env: &Env, profile_id: u32) -> Result<RiskProfile, Error> {
+        Self::get_risk_profiles(env.clone())
+            .get(profile_id)
+            .ok_or(Error::InvalidRiskLevel)
+    }
+
+    // Reject re-initialization once an admin has been stored
+    fn ensure_not_initialized(env: &Env) -> Result<(), Error> {
+        if env.storage().instance().has(&DataKey::Admin) {
+            return Err(Error::AlreadyInitialized);
+        }
+        Ok(())
+    }
+
+    // Load the stored admin and require its signature
+    fn require_admin(env: &Env) -> Result<Address, Error> {
+        let admin: Address = env.storage().instance()
+            .get(&DataKey::Admin)
+            .ok_or(Error::NotInitialized)?;
+        admin.require_auth();
+        Ok(admin)
+    }
+
+    fn ensure_not_paused(env: &Env, operation: Operation) -> Result<(), Error> {
+        if Self::is_paused(env.clone(), operation) {
+            return Err(Error::Paused);
+        }
+        Ok(())
+    }
+
+    fn set_paused(env: &Env, operation: Operation, paused: bool) {
+        let key = DataKey::Paused(operation);
+        if paused {
+            env.storage().instance().set(&key, &());
+        } else {
+            env.storage().instance().remove(&key);
+        }
+        events::pause_changed(env, operation, paused);
+    }
+
+    // Read a configured contract address from instance storage
+    fn get_address(env: &Env, key: &DataKey) -> Result<Address, Error> {
+        env.storage().instance().get(key).ok_or(Error::NotInitialized)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use soroban_sdk::{testutils::Address as _, testutils::Events, testutils::Ledger, Address, Env, Symbol};
+    use soroban_sdk::{contract, contractimpl, symbol_short, token, vec, Bytes};
+
+    // Router test double that swaps at fixed per-token prices out of its own balances,
+    // acting as the pair for every token pair
+    #[contract]
+    pub struct MockRouterContract;
+
+    #[contractimpl]
+    impl MockRouterContract {
+        pub fn set_price(env: Env, token: Address, price: i128) {
+            env.storage().instance().set(&token, &price);
+        }
+
+        pub fn router_pair_for(env: Env, _token_a: Address, _token_b: Address) -> Address {
+            env.current_contract_address()
+        }
+
+        pub fn swap_exact_tokens_for_tokens(
+            env: Env,
+            amount_in: i128,
+            amount_out_min: i128,
+            path: Vec<Address>,
+            to: Address,
+            _deadline: u64,
+        ) -> Vec<i128> {
+            let token_in = path.get(0).unwrap();
+            let token_out = path.get(1).unwrap();
+            let price_in: i128 = env.storage().instance().get(&token_in).unwrap();
+            let price_out: i128 = env.storage().instance().get(&token_out).unwrap();
+
+            let amount_out = amount_in * price_in / price_out;
+            assert!(amount_out >= amount_out_min, "insufficient output amount");
+            to.require_auth();
+
+            token::Client::new(&env, &token_in).transfer(&to, &env.current_contract_address(), &amount_in);
+            token::Client::new(&env, &token_out).transfer(&env.current_contract_address(), &to, &amount_out);
+            vec![&env, amount_in, amount_out]
+        }
+    }
+
+    // Token test double with configurable decimals; Stellar asset contracts always have 7
+    #[contract]
+    pub struct MockTokenContract;
+
+    #[contractimpl]
+    impl MockTokenContract {
+        pub fn set_decimals(env: Env, decimals: u32) {
+            env.storage().instance().set(&symbol_short!("decimals"), &decimals);
+        }
+
+        pub fn decimals(env: Env) -> u32 {
+            env.storage().instance().get(&symbol_short!("decimals")).unwrap()
+        }
+
+        pub fn mint(env: Env, to: Address, amount: i128) {
+            let balance = Self::balance(env.clone(), to.clone());
+            env.storage().instance().set(&to, &(balance + amount));
+        }
+
+        pub fn balance(env: Env, id: Address) -> i128 {
+            env.storage().instance().get(&id).unwrap_or(0)
+        }
+
+        pub fn transfer(env: Env, from: Address, to: Address, amount: i128) {
+            from.require_auth();
+            let balance = Self::balance(env.clone(), from.clone());
+            assert!(balance >= amount, "insufficient balance");
+            env.storage().instance().set(&from, &(balance - amount));
+            Self::mint(env, to, amount);
+        }
+    }
+
+    // Register a funded mock router priced like the mock oracle and point the fund at it
+    fn setup_mock_router(env: &Env, client: &KalePensionFundClient, kale_token: &Address, usdc_token: &Address, btc_token: &Address) -> Address {
+        let router_id = env.register_contract(None, MockRouterContract);
+        let router = MockRouterContractClient::new(env, &router_id);
+        router.set_price(kale_token, &120_000_000);
+        router.set_price(usdc_token, &1_000_000_000);
+        router.set_price(btc_token, &111_235_000_000_000);
+
+        mint_tokens_to_user(env, &router_id, kale_token, usdc_token, btc_token);
+        client.set_router(&router_id);
+        router_id
+    }
+
+    fn setup_test_environment() -> (Env, KalePensionFundClient<'static>, Address, Address, Address, Address, Address, Address, Address) {
+        let env = Env::default();
+        env.mock_all_auths();
+
+        let contract_id = env.register_contract(None, KalePensionFund);
+        let client = KalePensionFundClient::new(&env, &contract_id);
+
+        let admin = Address::generate(&env);
+        let user = Address::generate(&env);
+        
+        // Create proper mock token contracts
+        let kale_token = env.register_stellar_asset_contract_v2(admin.clone()).address();
+        let usdc_token = env.register_stellar_asset_contract_v2(admin.clone()).address();
+        let btc_token = env.register_stellar_asset_contract_v2(admin.clone()).address();
+        
+        // Create mock oracle contract for testing
+        let mock_oracle_id = env.register_contract(None, MockOracleContract);
+        let mock_oracle_client = MockOracleClient::new(&env, &mock_oracle_id);
+        
+        // Set up initial oracle prices with current market values
+        mock_oracle_client.set_price(&Symbol::new(&env, "KALE"), &120_000_000); // $0.12
+        mock_oracle_client.set_price(&Symbol::new(&env, "BTC"), &111_235_000_000_000); // ~$111,235 (current market price)
+        mock_oracle_client.set_price(&Symbol::new(&env, "USDC"), &1_000_000_000); // $1.00
+        
+        // Create router address (not needed for price testing but required for initialization)
+        let router = Address::generate(&env);
+
+        // Initialize contract with mock oracle
+        client.initialize(&admin, &kale_token, &usdc_token, &btc_token, &mock_oracle_id, &router);
+        
+        // Store mock oracle address for testing (using contract context)
+        env.as_contract(&contract_id, || {
+            env.storage().instance().set(&DataKey::MockOracleAddress, &mock_oracle_id);
+        });
+
+        // Setup initial token balances for testing
+        let kale_client = token::StellarAssetClient::new(&env, &kale_token);
+        let usdc_client = token::StellarAssetClient::new(&env, &usdc_token);
+        let btc_client = token::StellarAssetClient::new(&env, &btc_token);
+
+        // Mint tokens to user for testing
+        kale_client.mint(&user, &10_000_000_000_000); // 10,000 KALE
+        usdc_client.mint(&user, &10_000_000_000_000); // 10,000 USDC
+        btc_client.mint(&user, &100_000_000_000);    // 100 BTC
+        
+        // Mint tokens to contract for withdrawal testing
+        kale_client.mint(&contract_id, &10_000_000_000_000);
+        usdc_client.mint(&contract_id, &10_000_000_000_000);
+        btc_client.mint(&contract_id, &100_000_000_000);
+
+        (env, client, admin, user, kale_token, usdc_token, btc_token, mock_oracle_id, router)
+    }
+
+    fn mint_tokens_to_user(env: &Env, user: &Address, kale_token: &Address, usdc_token: &Address, btc_token: &Address) {
+        let kale_client = token::StellarAssetClient::new(env, kale_token);
+        let usdc_client = token::StellarAssetClient::new(env, usdc_token);
+        let btc_client = token::StellarAssetClient::new(env, btc_token);
+
+        kale_client.mint(user, &10_000_000_000_000); // 10,000 KALE
+        usdc_client.mint(user, &10_000_000_000_000); // 10,000 USDC
+        btc_client.mint(user, &100_000_000_000);    // 100 BTC
+    }
+
+    #[test]
+    fn test_initialize() {
+        let (env, client, _admin, _user, kale_token, _usdc_token, _btc_token, _oracle, _router) = setup_test_environment();
+
+        // Verify initialization was successful by checking we can get the portfolio (which requires tokens to be set)
+        let user = Address::generate(&env);
+        let portfolio = client.get_portfolio(&user);
+        assert_eq!(portfolio.balance(&kale_token), 0);
+        assert_eq!(portfolio.risk_level, 1);
+    }
+
+    #[test]
+    fn test_get_empty_portfolio() {
+        let (_env, client, _admin, user, kale_token, usdc_token, btc_token, _oracle, _router) = setup_test_environment();
+
+        let portfolio = client.get_portfolio(&user);
+        assert_eq!(portfolio.user, user);
+        assert_eq!(portfolio.balance(&kale_token), 0);
+        assert_eq!(portfolio.balance(&usdc_token), 0);
+        assert_eq!(portfolio.balance(&btc_token), 0);
+        assert_eq!(portfolio.risk_level, 1);
+    }
+
+    #[test]
+    fn test_deposit_kale() {
+        let (_env, client, _admin, user, kale_token, _usdc_token, _btc_token, _oracle, _router) = setup_test_environment();
+
+        // Mock the token transfer (in real tests we would need actual tokens)
+        // For unit tests, we focus on the contract logic
+        
+        // Deposit KALE (the transfer is mocked)
+        client.deposit(&user, &500_000_000_000); // 500 KALE
+
+        // Check portfolio
+        let portfolio = client.get_portfolio(&user);
+        assert_eq!(portfolio.balance(&kale_token), 500_000_000_000);
+        assert_eq!(portfolio.risk_level, 1); // Default conservative
+    }
+
+    #[test]
+    fn test_deposit_negative_amount() {
+        let (_env, client, _admin, user, kale_token, _usdc_token, _btc_token, _oracle, _router) = setup_test_environment();
+
+        let result = client.try_deposit(&user, &-100);
+        assert_eq!(result, Err(Ok(Error::InvalidAmount)));
+        
+        // Verify no portfolio was created
+        let portfolio = client.get_portfolio(&user);
+        assert_eq!(portfolio.balance(&kale_token), 0);
+    }
+
+    #[test]
+    fn test_deposit_zero_amount() {
+        let (_env, client, _admin, user, kale_token, _usdc_token, _btc_token, _oracle, _router) = setup_test_environment();
+
+        let result = client.try_deposit(&user, &0);
+        assert_eq!(result, Err(Ok(Error::InvalidAmount)));
+        
+        // Verify no portfolio was created
+        let portfolio = client.get_portfolio(&user);
+        assert_eq!(portfolio.balance(&kale_token), 0);
+    }
+
+    #[test]
+    fn test_multiple_deposits() {
+        let (_env, client, _admin, user, kale_token, _usdc_token, _btc_token, _oracle, _router) = setup_test_environment();
+
+        // First deposit
+        client.deposit(&user, &300_000_000_000);
+        let portfolio = client.get_portfolio(&user);
+        assert_eq!(portfolio.balance(&kale_token), 300_000_000_000);
+
+        // Second deposit
+        client.deposit(&user, &200_000_000_000);
+        let portfolio = client.get_portfolio(&user);
+        assert_eq!(portfolio.balance(&kale_token), 500_000_000_000);
+    }
+
+    #[test]
+    fn test_deposit_usdc_and_btc() {
+        let (env, client, _admin, user, kale_token, usdc_token, btc_token, _oracle, _router) = setup_test_environment();
+
+        client.deposit_asset(&user, &usdc_token, &250_000_000_000);
+        client.deposit_asset(&user, &btc_token, &5_000_000_000);
+        client.deposit_asset(&user, &kale_token, &100_000_000_000);
+
+        let portfolio = client.get_portfolio(&user);
+        assert_eq!(portfolio.balance(&usdc_token), 250_000_000_000);
+        assert_eq!(portfolio.balance(&btc_token), 5_000_000_000);
+        assert_eq!(portfolio.balance(&kale_token), 100_000_000_000);
+
+        // Tokens actually moved from the user
+        let usdc = token::Client::new(&env, &usdc_token);
+        assert_eq!(usdc.balance(&user), 10_000_000_000_000 - 250_000_000_000);
+    }
+
+    #[test]
+    fn test_deposit_asset_rejects_unknown_token() {
+        let (env, client, admin, user, _kale_token, usdc_token, _btc_token, _oracle, _router) = setup_test_environment();
+
+        let other_token = env.register_stellar_asset_contract_v2(admin).address();
+        let result = client.try_deposit_asset(&user, &other_token, &100);
+        assert_eq!(result, Err(Ok(Error::UnsupportedAsset)));
+
+        let result = client.try_deposit_asset(&user, &usdc_token, &0);
+        assert_eq!(result, Err(Ok(Error::InvalidAmount)));
+    }
+
+    #[test]
+    fn test_set_risk_levels() {
+        let (_env, client, _admin, user, _kale_token, _usdc_token, _btc_token, _oracle, _router) = setup_test_environment();
+
+        // Deposit KALE first
+        client.deposit(&user, &500_000_000_000);
+
+        // Test conservative (1)
+        client.set_risk(&user, &1);
+        let portfolio = client.get_portfolio(&user);
+        assert_eq!(portfolio.risk_level, 1);
+
+        // Test moderate (2)
+        client.set_risk(&user, &2);
+        let portfolio = client.get_portfolio(&user);
+        assert_eq!(portfolio.risk_level, 2);
+
+        // Test aggressive (3)
+        client.set_risk(&user, &3);
+        let portfolio = client.get_portfolio(&user);
+        assert_eq!(portfolio.risk_level, 3);
+    }
+
+    #[test]
+    fn test_invalid_risk_level_zero() {
+        let (_env, client, _admin, user, _kale_token, _usdc_token, _btc_token, _oracle, _router) = setup_test_environment();
+
+        // Deposit KALE first
+        client.deposit(&user, &500_000_000_000);
+        let portfolio_before = client.get_portfolio(&user);
+        let initial_risk_level = portfolio_before.risk_level;
+
+        let result = client.try_set_risk(&user, &0);
+        assert_eq!(result, Err(Ok(Error::InvalidRiskLevel)));
+        
+        // Verify risk level wasn't changed
+        let portfolio_after = client.get_portfolio(&user);
+        assert_eq!(portfolio_after.risk_level, initial_risk_level);
+    }
+
+    #[test]
+    fn test_invalid_risk_level_four() {
+        let (_env, client, _admin, user, _kale_token, _usdc_token, _btc_token, _oracle, _router) = setup_test_environment();
+
+        // Deposit KALE first
+        client.deposit(&user, &500_000_000_000);
+        let portfolio_before = client.get_portfolio(&user);
+        let initial_risk_level = portfolio_before.risk_level;
+
+        let result = client.try_set_risk(&user, &4);
+        assert_eq!(result, Err(Ok(Error::InvalidRiskLevel)));
+        
+        // Verify risk level wasn't changed
+        let portfolio_after = client.get_portfolio(&user);
+        assert_eq!(portfolio_after.risk_level, initial_risk_level);
+    }
+
+    #[test]
+    fn test_set_risk_without_portfolio() {
+        let (_env, client, _admin, user, _kale_token, _usdc_token, _btc_token, _oracle, _router) = setup_test_environment();
+
+        let result = client.try_set_risk(&user, &2);
+        assert_eq!(result, Err(Ok(Error::PortfolioNotFound)));
+    }
+
+    #[test]
+    fn test_get_price() {
+        let (_env, client, _admin, _user, _kale_token, _usdc_token, _btc_token, _oracle, _router) = setup_test_environment();
+
+        // Test KALE_USD price
+        let kale_price = client.get_price(&Symbol::new(&_env, "KALE_USD"));
+        assert_eq!(kale_price, 120_000_000); // 0.12 USDC
+
+        // Test BTC_USD price
+        let btc_price = client.get_price(&Symbol::new(&_env, "BTC_USD"));
+        assert_eq!(btc_price, 111_235_000_000_000); // ~$111,235 (current market price)
+    }
+
+    #[test]
+    fn test_get_price_unsupported_pair() {
+        let (env, client, _admin, _user, _kale_token, _usdc_token, _btc_token, oracle, _router) = setup_test_environment();
+
+        // No registered asset is priced as ETH_USD, so there is nothing to ask the oracle
+        let pair = Symbol::new(&env, "ETH_USD");
+        for _ in 0..3 {
+            env.ledger().with_mut(|l| l.timestamp += 301);
+            assert_eq!(client.try_get_price(&pair), Err(Ok(Error::UnsupportedPair)));
+        }
+
+        // Unsupported pairs never count as oracle failures, so one real failure leaves the circuit closed
+        MockOracleClient::new(&env, &oracle).set_price(&Symbol::new(&env, "KALE"), &0);
+        assert_eq!(client.get_price(&Symbol::new(&env, "KALE_USD")), 120_000_000);
+        let circuit_open: Vec<soroban_sdk::Val> = (symbol_short!("circuit"), symbol_short!("open")).into_val(&env);
+        assert!(!env.events().all().iter().any(|(_, topics, _)| topics == circuit_open));
+    }
+
+    #[test]
+    fn test_average_price_prefers_oracle_twap() {
+        let (env, client, _admin, _user, _kale_token, _usdc_token, _btc_token, oracle, _router) = setup_test_environment();
+        let oracle = MockOracleClient::new(&env, &oracle);
+        let pair = Symbol::new(&env, "KALE_USD");
+
+        // Prices recorded here, each held for 301 seconds, average to $0.15
+        client.get_price(&pair);
+        oracle.set_price(&Symbol::new(&env, "KALE"), &180_000_000);
+        env.ledger().with_mut(|l| l.timestamp += 301);
+        client.get_price(&pair);
+        env.ledger().with_mut(|l| l.timestamp += 301);
+
+        // Local history stands in while the oracle serves no TWAP
+        assert_eq!(client.get_averaging_source(), AveragingSource::OracleTwap(2_016));
+        assert_eq!(client.get_7day_average_price(&pair), 150_000_000);
+        oracle.set_twap(&Symbol::new(&env, "KALE"), &130_000_000);
+        assert_eq!(client.get_7day_average_price(&pair), 130_000_000);
+
+        // TWAPs are normalized like any other oracle price
+        oracle.set_decimals(&14);
+        oracle.set_twap(&Symbol::new(&env, "KALE"), &14_000_000_000_000);
+        assert_eq!(client.get_7day_average_price(&pair), 140_000_000);
+
+        assert_eq!(client.try_set_averaging_source(&AveragingSource::OracleTwap(0)), Err(Ok(Error::InvalidConfig)));
+        client.set_averaging_source(&AveragingSource::LocalHistory);
+        assert_eq!(
+            env.events().all().slice(env.events().all().len() - 1..),
+            vec![
+                &env,
+                (
+                    client.address.clone(),
+                    (symbol_short!("config"), symbol_short!("averaging")).into_val(&env),
+                    AveragingSource::LocalHistory.into_val(&env)
+                ),
+            ]
+        );
+        assert_eq!(client.get_7day_average_price(&pair), 150_000_000);
+    }
+
+    #[test]
+    fn test_local_price_history_is_time_weighted() {
+        let (env, client, _admin, _user, _kale_token, _usdc_token, _btc_token, oracle, _router) = setup_test_environment();
+        let oracle = MockOracleClient::new(&env, &oracle);
+        let pair = Symbol::new(&env, "KALE_USD");
+        client.set_averaging_source(&AveragingSource::LocalHistory);
+
+        // $0.12 for ten hours, then a burst of $0.60 prices over 25 minutes
+        let start = 100 * 3_600;
+        env.ledger().with_mut(|l| l.timestamp = start);
+        client.get_price(&pair);
+        env.ledger().with_mut(|l| l.timestamp += 36_000);
+        oracle.set_price(&Symbol::new(&env, "KALE"), &600_000_000);
+        for _ in 0..5 {
+            client.get_price(&pair);
+            env.ledger().with_mut(|l| l.timestamp += 301);
+        }
+
+        // Each price counts for as long as it held, however often it was recorded
+        let expected = (120_000_000 * 36_000 + 600_000_000 * 1_505) / 37_505;
+        assert_eq!(client.get_7day_average_price(&pair), expected);
+
+        let history = client.get_price_history(&pair, &0, &u64::MAX);
+        assert_eq!(history.len(), 2);
+        let (first, last) = (history.get_unchecked(0), history.get_unchecked(1));
+        assert_eq!((first.start, first.average(), first.seconds), (start, 120_000_000, 3_600));
+        assert_eq!((last.start, last.close, last.average()), (start + 36_000, 600_000_000, 600_000_000));
+        assert_eq!(client.get_price_history(&pair, &(start + 1), &(start + 36_000)), vec![&env, last]);
+
+        // Storage stays bounded to the hours of the last 7 days
+        env.budget().reset_unlimited();
+        for _ in 0..200 {
+            env.ledger().with_mut(|l| l.timestamp += 3_600);
+            client.get_price(&pair);
+        }
+        // Recording into a full history fits in one transaction's budget
+        env.budget().reset_default();
+        env.ledger().with_mut(|l| l.timestamp += 3_600);
+        client.get_price(&pair);
+        let history = client.get_price_history(&pair, &0, &u64::MAX);
+        assert_eq!(history.len(), 168);
+        assert!(history.get_unchecked(0).start >= env.ledger().timestamp() - 7 * 24 * 3_600);
+    }
+
+    #[test]
+    fn test_demo_price_guess() {
+        let (_env, client, _admin, _user, _kale_token, _usdc_token, _btc_token, _oracle, _router) = setup_test_environment();
+
+        // Test BTC price guess (should always return true in our demo since we simulate +1% increase)
+        let result = client.demo_price_guess(&Symbol::new(&_env, "BTC_USD"), &true);
+        assert!(result); // Should be correct since we guess "will rise" and simulate +1%
+
+        // Test guessing it will fall (should be false since we simulate +1% increase)
+        let result = client.demo_price_guess(&Symbol::new(&_env, "BTC_USD"), &false);
+        assert!(!result); // Should be incorrect since we guess "will fall" but simulate +1%
+    }
+
+    #[test]
+    fn test_debug_contract_status() {
+        let (_env, client, _admin, _user, kale_token, usdc_token, btc_token, _oracle, _router) = setup_test_environment();
+
+        // Test contract debug status
+        let (is_initialized, kale_addr, assets) = client.debug_contract_status();
+
+        assert!(is_initialized);
+        assert_eq!(kale_addr, Some(kale_token.clone()));
+        assert_eq!(assets.len(), 3);
+        assert!(assets.contains_key(kale_token) && assets.contains_key(usdc_token) && assets.contains_key(btc_token));
+    }
+
+    #[test]
+    fn test_initialize_twice_fails() {
+        let (env, client, _admin, _user, kale_token, usdc_token, btc_token, oracle, router) = setup_test_environment();
+
+        // A second initialize must not overwrite the configuration
+        let attacker = Address::generate(&env);
+        let result = client.try_initialize(&attacker, &kale_token, &usdc_token, &btc_token, &oracle, &router);
+        assert_eq!(result, Err(Ok(Error::AlreadyInitialized)));
+
+        let result = client.try_initialize_testnet(&attacker);
+        assert_eq!(result, Err(Ok(Error::AlreadyInitialized)));
+    }
+
+    #[test]
+    fn test_initialize_requires_token_decimals() {
+        let env = Env::default();
+        env.mock_all_auths();
+        let client = KalePensionFundClient::new(&env, &env.register_contract(None, KalePensionFund));
+        let admin = Address::generate(&env);
+        let kale_token = env.register_stellar_asset_contract_v2(admin.clone()).address();
+        let usdc_token = env.register_stellar_asset_contract_v2(admin.clone()).address();
+        let (oracle, router) = (Address::generate(&env), Address::generate(&env));
+
+        // No token contract answers for BTC, so its decimals can't be read
+        let missing = Address::generate(&env);
+        let result = client.try_initialize(&admin, &kale_token, &usdc_token, &missing, &oracle, &router);
+        assert_eq!(result, Err(Ok(Error::UnsupportedAsset)));
+        assert_eq!(client.get_admin(), None);
+
+        let btc_token = env.register_stellar_asset_contract_v2(admin.clone()).address();
+        client.initialize(&admin, &kale_token, &usdc_token, &btc_token, &oracle, &router);
+        assert_eq!(client.get_assets().get(btc_token).unwrap().decimals, 7);
+    }
+
+    #[test]
+    fn test_admin_setters() {
+        let (env, client, admin, _user, _kale_token, _usdc_token, _btc_token, _oracle, _router) = setup_test_environment();
+
+        assert_eq!(client.get_admin(), Some(admin.clone()));
+
+        let new_oracle = Address::generate(&env);
+        client.set_oracle(&new_oracle);
+        assert_eq!(env.auths()[0].0, admin);
+
+        let new_router = Address::generate(&env);
+        client.set_router(&new_router);
+        assert_eq!(env.auths()[0].0, admin);
+
+        let new_asset = env.register_stellar_asset_contract_v2(admin.clone()).address();
+        let config = AssetConfig {
+            price_pair: Symbol::new(&env, "EURC_USD"),
+            oracle_asset: reflector::Asset::Other(Symbol::new(&env, "EURC")),
+            decimals: 7,
+            enabled: true,
+        };
+        client.set_asset(&new_asset, &config);
+        assert_eq!(env.auths()[0].0, admin);
+        assert_eq!(client.get_assets().get(new_asset), Some(config));
+    }
+
+    #[test]
+    fn test_asset_registry_adds_assets_without_code_changes() {
+        let (env, client, _admin, user, kale_token, usdc_token, btc_token, oracle, _router) = setup_test_environment();
+
+        // A fourth asset only needs a registry entry and an oracle price
+        let eurc_token = env.register_stellar_asset_contract_v2(Address::generate(&env)).address();
+        token::StellarAssetClient::new(&env, &eurc_token).mint(&user, &1_000_000_000_000);
+        MockOracleClient::new(&env, &oracle).set_price(&Symbol::new(&env, "EURC"), &1_080_000_000);
+        let mut config = AssetConfig {
+            price_pair: Symbol::new(&env, "USDC_USD"),
+            oracle_asset: reflector::Asset::Other(Symbol::new(&env, "EURC")),
+            decimals: 7,
+            enabled: true,
+        };
+        assert_eq!(client.try_deposit_asset(&user, &eurc_token, &1_000), Err(Ok(Error::UnsupportedAsset)));
+        assert_eq!(client.try_set_asset(&eurc_token, &config), Err(Ok(Error::InvalidConfig)));
+        config.price_pair = Symbol::new(&env, "EURC_USD");
+        client.set_asset(&eurc_token, &config);
+        assert_eq!(
+            env.events().all().slice(env.events().all().len() - 1..),
+            vec![
+                &env,
+                (
+                    client.address.clone(),
+                    (symbol_short!("config"), symbol_short!("asset"), eurc_token.clone()).into_val(&env),
+                    config.clone().into_val(&env)
+                ),
+            ]
+        );
+
+        client.deposit_asset(&user, &eurc_token, &100_000_000_000);
+        let portfolio = client.get_portfolio(&user);
+        assert_eq!(portfolio.balance(&eurc_token), 100_000_000_000);
+        assert_eq!(portfolio.shares, 108_000_000_000);
+
+        // Profiles can target it alongside the original assets
+        let profile = RiskProfile {
+            name: String::from_str(&env, "Euro"),
+            targets: Map::from_array(&env, [
+                (kale_token.clone(), 1_000),
+                (usdc_token.clone(), 3_000),
+                (btc_token.clone(), 1_000),
+                (eurc_token.clone(), 5_000),
+            ]),
+        };
+        client.set_risk_profile(&4, &profile);
+        client.set_risk(&user, &4);
+        assert_eq!(client.get_pool(&4).balance(&eurc_token), 100_000_000_000);
+
+        // Disabled assets take no deposits or new targets but can still be withdrawn
+        config.enabled = false;
+        client.set_asset(&eurc_token, &config);
+        assert_eq!(client.try_deposit_asset(&user, &eurc_token, &1_000), Err(Ok(Error::UnsupportedAsset)));
+        assert_eq!(client.try_set_risk_profile(&5, &profile), Err(Ok(Error::UnsupportedAsset)));
+        client.withdraw(&user);
+        assert_eq!(token::Client::new(&env, &eurc_token).balance(&user), 1_000_000_000_000);
+    }
+
+    #[test]
+    fn test_registered_asset_without_fallback_price_is_unavailable() {
+        let (env, client, _admin, user, _kale_token, _usdc_token, _btc_token, oracle, _router) = setup_test_environment();
+        let eurc_token = env.register_stellar_asset_contract_v2(Address::generate(&env)).address();
+        token::StellarAssetClient::new(&env, &eurc_token).mint(&user, &1_000_000_000_000);
+        let eurc = Symbol::new(&env, "EURC");
+        let pair = Symbol::new(&env, "EURC_USD");
+        MockOracleClient::new(&env, &oracle).set_price(&eurc, &1_080_000_000);
+        client.set_asset(&eurc_token, &AssetConfig {
+            price_pair: pair.clone(),
+            oracle_asset: reflector::Asset::Other(eurc.clone()),
+            decimals: 7,
+            enabled: true,
+        });
+        assert_eq!(client.get_price(&pair), 1_080_000_000);
+
+        // Once the oracle is down and the cached price is too old, it has no price rather than $1
+        MockOracleClient::new(&env, &oracle).set_price(&eurc, &0);
+        env.ledger().with_mut(|l| l.timestamp += 3_601);
+        assert_eq!(client.try_get_price(&pair), Err(Ok(Error::OracleUnavailable)));
+        assert_eq!(client.try_deposit_asset(&user, &eurc_token, &1_000), Err(Ok(Error::OracleUnavailable)));
+    }
+
+    #[test]
+    fn test_prices_and_amounts_are_normalized_across_decimals() {
+        let (env, client, _admin, user, _kale_token, _usdc_token, _btc_token, oracle, _router) = setup_test_environment();
+
+        // Reflector quotes with 14 decimals; prices are normalized to PRICE_SCALE all the same
+        let oracle = MockOracleClient::new(&env, &oracle);
+        oracle.set_decimals(&14);
+        oracle.set_price(&Symbol::new(&env, "KALE"), &12_000_000_000_000);
+        oracle.set_price(&Symbol::new(&env, "ETH"), &200_000_000_000_000_000); // $2,000
+        assert_eq!(client.get_price(&Symbol::new(&env, "KALE_USD")), 120_000_000);
+
+        // An 18-decimal token must be registered with the decimals it reports
+        let eth_token = env.register_contract(None, MockTokenContract);
+        let eth = MockTokenContractClient::new(&env, &eth_token);
+        eth.set_decimals(&18);
+        eth.mint(&user, &2_000_000_000_000_000_000);
+        let mut config = AssetConfig {
+            price_pair: Symbol::new(&env, "ETH_USD"),
+            oracle_asset: reflector::Asset::Other(Symbol::new(&env, "ETH")),
+            decimals: 7,
+            enabled: true,
+        };
+        assert_eq!(client.try_set_asset(&eth_token, &config), Err(Ok(Error::InvalidConfig)));
+        config.decimals = 18;
+        client.set_asset(&eth_token, &config);
+
+        // Anything that is not a token can't be registered
+        config.price_pair = Symbol::new(&env, "XLM_USD");
+        assert_eq!(client.try_set_asset(&Address::generate(&env), &config), Err(Ok(Error::UnsupportedAsset)));
+
+        // 1.5 ETH is worth $3,000, i.e. 30_000_000_000 USDC base units and as many shares
+        client.deposit_asset(&user, &eth_token, &1_500_000_000_000_000_000);
+        assert_eq!(client.get_portfolio(&user).shares, 30_000_000_000);
+
+        // Withdrawing a third of it burns a third of the shares
+        client.withdraw_partial(&user, &eth_token, &500_000_000_000_000_000);
+        assert_eq!(client.get_portfolio(&user).shares, 20_000_000_000);
+        assert_eq!(eth.balance(&user), 1_000_000_000_000_000_000);
+
+        // Swaps between assets of different decimals are sized in each token's own units
+        let eth_quote = AssetQuote { price: 2_000 * PRICE_SCALE, decimals: 18, source: PriceSource::Oracle };
+        let usdc_quote = AssetQuote { price: PRICE_SCALE, decimals: 7, source: PriceSource::Oracle };
+        assert_eq!(eth_quote.convert(500_000_000_000_000_000, &usdc_quote, Rounding::Down), Ok(10_000_000_000));
+        assert_eq!(usdc_quote.convert(10_000_000_000, &eth_quote, Rounding::Down), Ok(500_000_000_000_000_000));
+        assert_eq!(eth_quote.convert(1, &usdc_quote, Rounding::Down), Ok(0));
+        assert_eq!(eth_quote.convert(1, &usdc_quote, Rounding::Up), Ok(1));
+    }
+
+    // a * b / denominator computed in 256 bits by the host, or None if it doesn't fit in i128
+    fn reference_mul_div(env: &Env, a: i128, b: i128, denominator: i128, rounding: Rounding) -> Option<i128> {
+        let zero = soroban_sdk::I256::from_i32(env, 0);
+        let mut product = soroban_sdk::I256::from_i128(env, a).mul(&soroban_sdk::I256::from_i128(env, b));
+        let mut divisor = soroban_sdk::I256::from_i128(env, denominator);
+        if denominator < 0 {
+            product = zero.sub(&product);
+            divisor = zero.sub(&divisor);
+        }
+        let remainder = product.rem_euclid(&divisor);
+        let mut quotient = product.sub(&remainder).div(&divisor);
+        if rounding == Rounding::Up && remainder != zero {
+            quotient = quotient.add(&soroban_sdk::I256::from_i32(env, 1));
+        }
+        quotient.to_i128()
+    }
+
+    fn random_i128(seed: &mut u64) -> i128 {
+        let mut next = || {
+            *seed ^= *seed << 13;
+            *seed ^= *seed >> 7;
+            *seed ^= *seed << 17;
+            *seed
+        };
+        let raw = (((next() as u128) << 64) | next() as u128) as i128;
+        // Shifting out a random number of bits spreads values over every magnitude
+        raw >> (next() % 128)
+    }
+
+    #[test]
+    fn test_mul_div_matches_wide_arithmetic() {
+        let env = Env::default();
+        env.budget().reset_unlimited();
+
+        let extremes = [
+            i128::MIN, i128::MIN + 1, -PRICE_SCALE, -1, 0, 1, 2, PRICE_SCALE,
+            1_000_000_000_000_000_000, 1 << 64, (1 << 64) - 1, i128::MAX - 1, i128::MAX,
+        ];
+        let check = |a: i128, b: i128, denominator: i128| {
+            for rounding in [Rounding::Down, Rounding::Up] {
+                let expected = if denominator == 0 {
+                    Err(Error::DivisionByZero)
+                } else {
+                    reference_mul_div(&env, a, b, denominator, rounding).ok_or(Error::Overflow)
+                };
+                assert_eq!(math::mul_div(a, b, denominator, rounding), expected, "{a} * {b} / {denominator} {rounding:?}");
+            }
+        };
+
+        for a in extremes {
+            for b in extremes {
+                for denominator in extremes {
+                    check(a, b, denominator);
+                }
+            }
+        }
+        let mut seed = 0x2545_f491_4f6c_dd1d;
+        for _ in 0..1_000 {
+            check(random_i128(&mut seed), random_i128(&mut seed), random_i128(&mut seed));
+        }
+    }
+
+    #[test]
+    fn test_valuations_never_overflow_silently() {
+        // 10^25 base units of BTC at ~$111,235 overflow i128 when multiplied directly
+        let btc = AssetQuote { price: 111_235 * PRICE_SCALE, decimals: 7, source: PriceSource::Oracle };
+        let balance: i128 = 10_000_000_000_000_000_000_000_000;
+        assert!(balance.checked_mul(btc.price).is_none());
+        assert_eq!(btc.value(balance, Rounding::Down), Ok(111_235 * balance));
+        assert_eq!(btc.amount(111_235 * balance, Rounding::Down), Ok(balance));
+
+        // Results that don't fit are errors, never wrapped or panicking
+        assert_eq!(btc.value(i128::MAX, Rounding::Down), Err(Error::Overflow));
+        assert_eq!(btc.value(i128::MIN, Rounding::Up), Err(Error::Overflow));
+        let usdc = AssetQuote { price: PRICE_SCALE, decimals: 7, source: PriceSource::Oracle };
+        assert_eq!(usdc.convert(i128::MAX, &btc, Rounding::Up), Ok(i128::MAX / 111_235 + 1));
+        let eth = AssetQuote { price: 2_000 * PRICE_SCALE, decimals: 38, source: PriceSource::Oracle };
+        assert_eq!(usdc.convert(1, &eth, Rounding::Down), Err(Error::Overflow));
+
+        // Rounding modes bracket the exact result
+        assert_eq!(math::mul_div(-7, 1, 2, Rounding::Down), Ok(-4));
+        assert_eq!(math::mul_div(-7, 1, 2, Rounding::Up), Ok(-3));
+        assert_eq!(math::mul_div(7, 1, -2, Rounding::Down), Ok(-4));
+        assert_eq!(math::mul_div(1, 1, 0, Rounding::Down), Err(Error::DivisionByZero));
+    }
+
+    #[test]
+    fn test_transfer_admin_two_step() {
+        let (env, client, admin, _user, _kale_token, _usdc_token, _btc_token, _oracle, _router) = setup_test_environment();
+
+        let new_admin = Address::generate(&env);
+        let stranger = Address::generate(&env);
+
+        // Accepting without a pending transfer fails
+        assert_eq!(client.try_accept_admin(&new_admin), Err(Ok(Error::Unauthorized)));
+
+        client.transfer_admin(&new_admin);
+        assert_eq!(client.get_admin(), Some(admin));
+
+        // Only the proposed admin can accept
+        assert_eq!(client.try_accept_admin(&stranger), Err(Ok(Error::Unauthorized)));
+
+        client.accept_admin(&new_admin);
+        assert_eq!(env.auths()[0].0, new_admin);
+        assert_eq!(client.get_admin(), Some(new_admin));
+    }
+
+    #[test]
+    fn test_deposit_and_risk_events() {
+        let (env, client, _admin, user, kale_token, _usdc_token, _btc_token, _oracle, _router) = setup_test_environment();
+
+        client.deposit(&user, &500_000_000_000);
+        let events = env.events().all();
+        assert_eq!(
+            events.slice(events.len() - 1..),
+            vec![
+                &env,
+                (
+                    client.address.clone(),
+                    (symbol_short!("deposit"), user.clone()).into_val(&env),
+                    (kale_token, 500_000_000_000i128).into_val(&env),
+                ),
+            ]
+        );
+
+        client.set_risk(&user, &3);
+        let events = env.events().all();
+        assert_eq!(
+            events.slice(events.len() - 1..),
+            vec![
+                &env,
+                (
+                    client.address.clone(),
+                    (symbol_short!("risk"), user.clone()).into_val(&env),
+                    (1u32, 3u32).into_val(&env),
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_oracle_failures_open_circuit() {
+        let (env, client, _admin, _user, _kale_token, _usdc_token, _btc_token, _oracle, _router) = setup_test_environment();
+
+        // The oracle stops serving valid KALE prices, so every uncached lookup is an oracle failure
+        MockOracleClient::new(&env, &_oracle).set_price(&Symbol::new(&env, "KALE"), &0);
+        let pair = Symbol::new(&env, "KALE_USD");
+        for _ in 0..3 {
+            env.ledger().with_mut(|l| l.timestamp += 301);
+            client.get_price(&pair);
+        }
+
+        let events = env.events().all();
+        assert_eq!(
+            events.slice(events.len() - 2..),
+            vec![
+                &env,
+                (
+                    client.address.clone(),
+                    (symbol_short!("circuit"), symbol_short!("open")).into_val(&env),
+                    3u32.into_val(&env),
+                ),
+                (
+                    client.address.clone(),
+                    (symbol_short!("fallback"), pair.clone()).into_val(&env),
+                    (120_000_000i128, PriceSource::Cache).into_val(&env),
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_shares_only_move_at_oracle_prices() {
+        let (env, client, _admin, user, kale_token, usdc_token, _btc_token, oracle, _router) = setup_test_environment();
+
+        client.deposit(&user, &500_000_000_000);
+        client.deposit_asset(&user, &usdc_token, &100_000_000_000);
+
+        // Without a live KALE price only the cached or fallback price is left
+        MockOracleClient::new(&env, &oracle).set_price(&Symbol::new(&env, "KALE"), &0);
+        env.ledger().with_mut(|l| l.timestamp += 301);
+        assert_eq!(client.try_deposit(&user, &1_000_000_000), Err(Ok(Error::OracleUnavailable)));
+        assert_eq!(
+            client.try_deposit_asset(&user, &usdc_token, &1_000_000_000),
+            Err(Ok(Error::OracleUnavailable))
+        );
+        assert_eq!(client.try_withdraw_value(&user, &1_000_000_000), Err(Ok(Error::OracleUnavailable)));
+        assert_eq!(
+            client.try_withdraw_partial(&user, &usdc_token, &1_000_000_000),
+            Err(Ok(Error::OracleUnavailable))
+        );
+
+        // Withdrawing everything in kind needs no price
+        client.withdraw(&user);
+        assert_eq!(token::Client::new(&env, &kale_token).balance(&user), 10_000_000_000_000);
+    }
+
+    #[test]
+    fn test_deposits_mint_shares_at_nav() {
+        let (env, client, _admin, user, kale_token, usdc_token, btc_token, oracle, _router) = setup_test_environment();
+
+        // 1000 KALE at $0.12 opens the pool at one share per USDC base unit
+        client.deposit(&user, &1_000_000_000_000);
+        assert_eq!(client.get_portfolio(&user).shares, 120_000_000_000);
+        assert_eq!(client.get_share_price(&1), 1_000_000_000);
+
+        // KALE doubles, so the NAV per share doubles too
+        MockOracleClient::new(&env, &oracle).set_price(&Symbol::new(&env, "KALE"), &240_000_000);
+        env.ledger().with_mut(|l| l.timestamp += 301);
+        assert_eq!(client.get_share_price(&1), 2_000_000_000);
+
+        // A later member buys in at the new NAV and owns a pro-rata slice of every holding
+        let member = Address::generate(&env);
+        mint_tokens_to_user(&env, &member, &kale_token, &usdc_token, &btc_token);
+        client.deposit_asset(&member, &usdc_token, &240_000_000_000);
+
+        let portfolio = client.get_portfolio(&member);
+        assert_eq!(portfolio.shares, 120_000_000_000);
+        assert_eq!(portfolio.balance(&kale_token), 500_000_000_000);
+        assert_eq!(portfolio.balance(&usdc_token), 120_000_000_000);
+
+        let pool = client.get_pool(&1);
+        assert_eq!(pool.balance(&kale_token), 1_000_000_000_000);
+        assert_eq!(pool.balance(&usdc_token), 240_000_000_000);
+        assert_eq!(pool.total_shares, 240_000_000_000);
+
+        // Early members keep the gain: withdrawing half their shares' value pays $120
+        client.withdraw_value(&user, &120_000_000_000);
+        assert_eq!(client.get_portfolio(&user).shares, 60_000_000_000);
+        assert_eq!(client.get_pool(&1).total_shares, 180_000_000_000);
+    }
+
+    #[test]
+    fn test_set_risk_moves_holdings_between_pools() {
+        let (env, client, _admin, user, kale_token, usdc_token, btc_token, _oracle, _router) = setup_test_environment();
+
+        let member = Address::generate(&env);
+        mint_tokens_to_user(&env, &member, &kale_token, &usdc_token, &btc_token);
+        client.deposit(&user, &1_000_000_000_000);
+        client.deposit(&member, &500_000_000_000);
+
+        // The member's slice leaves in kind and buys into the new tier at its NAV
+        client.set_risk(&user, &3);
+        let pool = client.get_pool(&1);
+        assert_eq!(pool.balance(&kale_token), 500_000_000_000);
+        assert_eq!(pool.total_shares, 60_000_000_000);
+
+        let pool = client.get_pool(&3);
+        assert_eq!(pool.balance(&kale_token), 1_000_000_000_000);
+        assert_eq!(pool.total_shares, 120_000_000_000);
+        assert_eq!(client.get_portfolio(&user).balance(&kale_token), 1_000_000_000_000);
+        assert_eq!(client.get_portfolio(&member).balance(&kale_token), 500_000_000_000);
+    }
+
+    #[test]
+    fn test_risk_profiles_are_configurable() {
+        let (env, client, _admin, user, kale_token, usdc_token, btc_token, _oracle, _router) = setup_test_environment();
+
+        let profiles = client.get_risk_profiles();
+        assert_eq!(profiles.len(), 3);
+        assert_eq!(profiles.get(1).unwrap().target_bps(&usdc_token), 7_000);
+
+        let mut profile = RiskProfile {
+            name: String::from_str(&env, "Growth"),
+            targets: Map::from_array(&env, [(kale_token.clone(), 5_000), (usdc_token.clone(), 2_000), (btc_token.clone(), 2_000)]),
+        };
+        assert_eq!(client.try_set_risk_profile(&4, &profile), Err(Ok(Error::InvalidConfig)));
+        let mut unregistered = profile.clone();
+        unregistered.targets.set(Address::generate(&env), 1_000);
+        assert_eq!(client.try_set_risk_profile(&4, &unregistered), Err(Ok(Error::UnsupportedAsset)));
+
+        profile.targets.set(btc_token.clone(), 3_000);
+        client.set_risk_profile(&4, &profile);
+        assert_eq!(
+            env.events().all().slice(env.events().all().len() - 1..),
+            vec![
+                &env,
+                (
+                    client.address.clone(),
+                    (symbol_short!("config"), symbol_short!("profile"), 4u32).into_val(&env),
+                    profile.clone().into_val(&env)
+                ),
+            ]
+        );
+        assert_eq!(client.get_risk_profiles().get(4), Some(profile));
+
+        client.deposit(&user, &1_000_000_000_000);
+        client.set_risk(&user, &4);
+        assert_eq!(client.get_portfolio(&user).risk_level, 4);
+        assert_eq!(client.get_pool(&4).balance(&kale_token), 1_000_000_000_000);
+        assert_eq!(client.try_set_risk(&user, &5), Err(Ok(Error::InvalidRiskLevel)));
+    }
+
+    #[test]
+    fn test_target_date_pool_follows_glide_path() {
+        let (env, client, _admin, user, kale_token, usdc_token, btc_token, _oracle, _router) = setup_test_environment();
+        let year = 31_536_000u64;
+
+        client.enroll(&user, &(5 * year));
+        client.deposit(&user, &1_000_000_000_000);
+        assert_eq!(client.try_use_target_date(&user), Err(Ok(Error::InvalidConfig)));
+
+        // Aggressive until ten years out, then gliding to conservative
+        assert_eq!(client.try_set_glide_path(&3, &9, &10), Err(Ok(Error::InvalidRiskLevel)));
+        client.set_glide_path(&3, &1, &10);
+        client.use_target_date(&user);
+
+        let pool_id = TARGET_DATE_POOLS + 5;
+        assert_eq!(client.get_portfolio(&user).risk_level, pool_id);
+        assert_eq!(client.get_pool(&pool_id).balance(&kale_token), 1_000_000_000_000);
+
+        // Halfway down the glide path
+        let allocation = client.get_target_allocation(&pool_id);
+        assert_eq!((allocation.target_bps(&kale_token), allocation.target_bps(&usdc_token), allocation.target_bps(&btc_token)), (2_000, 5_000, 3_000));
+
+        env.ledger().with_mut(|l| l.timestamp = 5 * year);
+        let allocation = client.get_target_allocation(&pool_id);
+        assert_eq!((allocation.target_bps(&kale_token), allocation.target_bps(&usdc_token), allocation.target_bps(&btc_token)), (1_000, 7_000, 2_000));
+
+        // Pushing retirement out moves the member to the later year's pool
+        client.extend_retirement(&user, &(20 * year));
+        let pool_id = TARGET_DATE_POOLS + 20;
+        assert_eq!(client.get_portfolio(&user).risk_level, pool_id);
+        assert_eq!(client.get_pool(&pool_id).balance(&kale_token), 1_000_000_000_000);
+        assert_eq!(client.get_pool(&(TARGET_DATE_POOLS + 5)).total_shares, 0);
+        let allocation = client.get_target_allocation(&pool_id);
+        assert_eq!((allocation.target_bps(&kale_token), allocation.target_bps(&usdc_token), allocation.target_bps(&btc_token)), (3_000, 3_000, 4_000));
+
+        // Years past the last pool id have no target-date pool
+        let last_year = (u32::MAX - TARGET_DATE_POOLS) as u64;
+        assert_eq!(client.try_extend_retirement(&user, &((last_year + 1) * year)), Err(Ok(Error::InvalidRetirementDate)));
+        assert_eq!(client.try_extend_retirement(&user, &u64::MAX), Err(Ok(Error::InvalidRetirementDate)));
+    }
+
+    #[test]
+    fn test_glide_path_targets_rounded_over_full_weight() {
+        let (_env, client, _admin, user, kale_token, usdc_token, btc_token, _oracle, _router) = setup_test_environment();
+        let year = 31_536_000u64;
+
+        // A third of the way along, conservative to aggressive interpolates to
+        // 2333.3 / 4333.3 / 3333.3, which truncates toward zero to 2334 / 4333 / 3334
+        client.enroll(&user, &(5 * year));
+        client.set_glide_path(&1, &3, &15);
+        client.use_target_date(&user);
+        let allocation = client.get_target_allocation(&(TARGET_DATE_POOLS + 5));
+        assert_eq!((allocation.target_bps(&kale_token), allocation.target_bps(&usdc_token), allocation.target_bps(&btc_token)), (2_334, 4_332, 3_334));
+        assert_eq!(allocation.targets.values().iter().sum::<u32>(), 10_000);
+    }
+
+    #[test]
+    fn test_share_token_reports_member_shares() {
+        let (env, client, _admin, user, _kale_token, _usdc_token, _btc_token, _oracle, _router) = setup_test_environment();
+
+        assert_eq!(client.decimals(), 7);
+        assert_eq!(client.symbol(), String::from_str(&env, "KPFS"));
+        assert_eq!(client.balance(&user), 0);
+
+        client.deposit(&user, &1_000_000_000_000);
+        assert_eq!(client.balance(&user), 120_000_000_000);
+
+        // Shares are non-transferable until the admin opens transfers
+        let other = Address::generate(&env);
+        assert_eq!(
+            client.try_transfer(&user, &other, &1),
+            Err(Ok(Error::TransfersDisabled.into()))
+        );
+        assert_eq!(client.try_burn(&user, &1), Err(Ok(Error::TransfersDisabled.into())));
+    }
+
+    #[test]
+    fn test_share_balance_is_a_pure_read() {
+        let (env, client, _admin, user, _kale_token, usdc_token, _btc_token, _oracle, _router) = setup_test_environment();
+
+        let employer = Address::generate(&env);
+        token::StellarAssetClient::new(&env, &usdc_token).mint(&employer, &10_000_000_000_000);
+        client.register_employer(&employer, &employer_terms());
+        client.contribute_for(&employer, &user, &usdc_token, &400_000_000_000);
+        assert_eq!(client.balance(&user), 0);
+
+        // Half vests after two years; the balance counts it before it is released
+        env.ledger().with_mut(|l| l.timestamp += 2 * 365 * 86_400);
+        let stored = || env.as_contract(&client.address, || {
+            env.storage().persistent().get::<DataKey, Portfolio>(&DataKey::Portfolio(user.clone())).unwrap()
+        });
+        let events = env.events().all().len();
+        assert_eq!(client.balance(&user), 200_000_000_000);
+        assert_eq!(env.events().all().len(), events);
+        let portfolio = stored();
+        assert_eq!((portfolio.shares, portfolio.employer_shares, portfolio.vested_bps), (0, 400_000_000_000, 0));
+
+        // The member's next action releases them
+        client.check_in(&user);
+        assert_eq!(stored().shares, 200_000_000_000);
+        assert_eq!(client.balance(&user), 200_000_000_000);
+    }
+
+    #[test]
+    fn test_share_transfers_follow_policy() {
+        let (env, client, _admin, user, kale_token, usdc_token, btc_token, _oracle, _router) = setup_test_environment();
+
+        let member = Address::generate(&env);
+        let newcomer = Address::generate(&env);
+        mint_tokens_to_user(&env, &member, &kale_token, &usdc_token, &btc_token);
+        client.deposit(&user, &1_000_000_000_000);
+        client.deposit(&member, &100_000_000_000);
+
+        client.set_share_transfer_policy(&TransferPolicy::MembersOnly);
+        assert_eq!(
+            client.try_transfer(&user, &newcomer, &1),
+            Err(Ok(Error::PortfolioNotFound.into()))
+        );
+
+        client.transfer(&user, &member, &20_000_000_000);
+        assert_eq!(
+            env.events().all().slice(env.events().all().len() - 1..),
+            vec![
+                &env,
+                (
+                    client.address.clone(),
+                    (symbol_short!("transfer"), user.clone(), member.clone()).into_val(&env),
+                    20_000_000_000i128.into_val(&env)
+                ),
+            ]
+        );
+        assert_eq!(client.balance(&user), 100_000_000_000);
+        assert_eq!(client.get_portfolio(&member).balance(&kale_token), 100_000_000_000 + 166_666_666_666);
+
+        // Spenders move shares within their allowance
+        client.set_share_transfer_policy(&TransferPolicy::Open);
+        let spender = Address::generate(&env);
+        client.approve(&user, &spender, &10_000_000_000, &1_000);
+        assert_eq!(
+            client.try_transfer_from(&spender, &user, &newcomer, &10_000_000_001),
+            Err(Ok(Error::InsufficientAllowance.into()))
+        );
+        let last_activity = client.get_portfolio(&user).last_activity;
+        env.ledger().with_mut(|l| l.timestamp += 86_400);
+        client.transfer_from(&spender, &user, &newcomer, &10_000_000_000);
+        assert_eq!(client.allowance(&user, &spender), 0);
+        assert_eq!(client.balance(&newcomer), 10_000_000_000);
+
+        // Only the owner's own transfers count as activity for the inheritance timer
+        assert_eq!(client.get_portfolio(&user).last_activity, last_activity);
+        client.transfer(&user, &newcomer, &1);
+        assert_eq!(client.get_portfolio(&user).last_activity, env.ledger().timestamp());
+
+        // Shares cannot cross risk tiers
+        client.set_risk(&member, &3);
+        assert_eq!(
+            client.try_transfer(&user, &member, &1),
+            Err(Ok(Error::RiskTierMismatch.into()))
+        );
+    }
+
+    #[test]
+    fn test_share_transfers_keep_lock_up() {
+        let (env, client, _admin, user, kale_token, usdc_token, btc_token, _oracle, _router) = setup_test_environment();
+
+        let reserve = Address::generate(&env);
+        client.set_retirement_policy(&0, &0, &reserve);
+        client.set_share_transfer_policy(&TransferPolicy::Open);
+
+        let retiree = Address::generate(&env);
+        mint_tokens_to_user(&env, &retiree, &kale_token, &usdc_token, &btc_token);
+        client.deposit(&retiree, &100_000_000_000);
+        client.enroll(&user, &1_000_000);
+        client.deposit(&user, &100_000_000_000);
+
+        // A locked member cannot hand shares to someone who could withdraw them sooner
+        assert_eq!(
+            client.try_transfer(&user, &retiree, &1),
+            Err(Ok(Error::RetirementLocked.into()))
+        );
+
+        // New recipients inherit the sender's lock-up
+        let newcomer = Address::generate(&env);
+        client.transfer(&user, &newcomer, &1_000_000_000);
+        assert_eq!(client.get_portfolio(&newcomer).retirement_date, 1_000_000);
+    }
+
+    #[test]
+    fn test_management_fee_accrues_over_time() {
+        let (env, client, _admin, user, kale_token, _usdc_token, _btc_token, _oracle, _router) = setup_test_environment();
+
+        let treasury = Address::generate(&env);
+        assert_eq!(
+            client.try_set_fee_config(&10_001, &0, &treasury, &FeeAsset::InKind),
+            Err(Ok(Error::InvalidConfig))
+        );
+        client.set_fee_config(&100, &0, &treasury, &FeeAsset::InKind); // 1% a year
+
+        client.deposit(&user, &1_000_000_000_000);
+        env.ledger().with_mut(|l| l.timestamp += 31_536_000);
+
+        let fees = client.get_accrued_fees(&1);
+        assert_eq!(fees.shares, 1_200_000_000);
+        assert_eq!(client.collect_fees(&1), 1_200_000_000);
+        assert_eq!(
+            env.events().all().slice(env.events().all().len() - 1..),
+            vec![
+                &env,
+                (
+                    client.address.clone(),
+                    (symbol_short!("fees"), treasury.clone(), 1u32).into_val(&env),
+                    (1_200_000_000i128, Map::from_array(&env, [(kale_token.clone(), 9_900_990_099i128)])).into_val(&env)
+                ),
+            ]
+        );
+
+        // The member is diluted by the fee; nothing else is owed until more time passes
+        let kale = token::Client::new(&env, &kale_token);
+        assert_eq!(kale.balance(&treasury), 9_900_990_099);
+        assert_eq!(client.get_portfolio(&user).balance(&kale_token), 1_000_000_000_000 - 9_900_990_099);
+        assert_eq!(client.get_accrued_fees(&1).shares, 0);
+    }
+
+    #[test]
+    fn test_performance_fee_above_high_water_mark() {
+        let (env, client, _admin, user, kale_token, _usdc_token, _btc_token, oracle, _router) = setup_test_environment();
+
+        let treasury = Address::generate(&env);
+        client.set_fee_config(&0, &2_000, &treasury, &FeeAsset::Kale); // 20% of gains
+        client.deposit(&user, &1_000_000_000_000);
+        assert_eq!(client.get_accrued_fees(&1).shares, 0);
+
+        // KALE doubles: the fee takes 20% of the $120 gain
+        MockOracleClient::new(&env, &oracle).set_price(&Symbol::new(&env, "KALE"), &240_000_000);
+        env.ledger().with_mut(|l| l.timestamp += 301);
+        let fees = client.get_accrued_fees(&1);
+        assert_eq!(fees.shares, 13_333_333_333);
+        assert_eq!(fees.value, 23_999_999_999);
+
+        client.collect_fees(&1);
+        let kale = token::Client::new(&env, &kale_token);
+        assert_eq!(kale.balance(&treasury), 99_999_999_995);
+        assert_eq!(client.get_pool(&1).high_water_mark, 1_800_000_000);
+
+        // No further fee until the NAV beats the new high-water mark
+        env.ledger().with_mut(|l| l.timestamp += 301);
+        assert_eq!(client.get_accrued_fees(&1).shares, 0);
+    }
+
+    #[test]
+    fn test_performance_fee_waits_for_live_prices() {
+        let (env, client, _admin, user, _kale_token, _usdc_token, btc_token, oracle, _router) = setup_test_environment();
+
+        let treasury = Address::generate(&env);
+        client.set_fee_config(&0, &2_000, &treasury, &FeeAsset::InKind);
+        let btc = Symbol::new(&env, "BTC");
+        MockOracleClient::new(&env, &oracle).set_price(&btc, &55_617_500_000_000);
+        client.deposit_asset(&user, &btc_token, &100_000_000);
+        let high_water_mark = client.get_pool(&1).high_water_mark;
+
+        // The fallback BTC price doubles the pool on paper, which is no gain to charge for
+        MockOracleClient::new(&env, &oracle).set_price(&btc, &0);
+        env.ledger().with_mut(|l| l.timestamp += 3_601);
+        assert_eq!(client.get_accrued_fees(&1).shares, 0);
+        assert_eq!(client.get_pool(&1).high_water_mark, high_water_mark);
+
+        // Loading the pool needs no price, so in-kind withdrawals stay open
+        client.withdraw(&user);
+        assert_eq!(token::Client::new(&env, &btc_token).balance(&user), 100_000_000_000);
+    }
+
+    #[test]
+    fn test_rebalance_no_change_needed() {
+        let (_env, client, _admin, user, kale_token, usdc_token, btc_token, _oracle, _router) = setup_test_environment();
+
+        // $100 of KALE, $700 of USDC and $200 of BTC: the conservative targets
+        client.deposit(&user, &8_333_333_333);
+        client.deposit_asset(&user, &usdc_token, &7_000_000_000);
+        client.deposit_asset(&user, &btc_token, &17_980);
+
+        // Get initial portfolio
+        let initial_portfolio = client.get_portfolio(&user);
+
+        // Rebalance (should not change anything as every weight is within the drift band)
+        client.rebalance(&user);
+
+        // Portfolio should remain the same
+        let final_portfolio = client.get_portfolio(&user);
+        assert_eq!(initial_portfolio.balance(&kale_token), final_portfolio.balance(&kale_token));
+        assert_eq!(initial_portfolio.balance(&usdc_token), final_portfolio.balance(&usdc_token));
+        assert_eq!(initial_portfolio.balance(&btc_token), final_portfolio.balance(&btc_token));
+    }
+
+    #[test]
+    fn test_rebalance_swaps_between_all_assets() {
+        let (env, client, _admin, user, kale_token, usdc_token, btc_token, _oracle, _router) = setup_test_environment();
+        setup_mock_router(&env, &client, &kale_token, &usdc_token, &btc_token);
+
+        // $300 of KALE and $700 of USDC against aggressive 30/30/40 targets
+        client.deposit(&user, &25_000_000_000);
+        client.set_risk(&user, &3);
+        client.deposit_asset(&user, &usdc_token, &7_000_000_000);
+
+        // Within a 50% band nothing trades
+        client.set_drift_band(&5_000);
+        client.rebalance(&user);
+        assert_eq!(client.get_pool(&3).balance(&usdc_token), 7_000_000_000);
+
+        // KALE is on target, so a single USDC -> BTC swap restores the allocation
+        assert_eq!(client.try_set_drift_band(&10_001), Err(Ok(Error::InvalidConfig)));
+        client.set_drift_band(&500);
+        let plan = client.preview_rebalance(&user);
+        assert_eq!(plan.price(&btc_token), 111_235_000_000_000);
+        assert_eq!(plan.quotes.get(kale_token.clone()).unwrap().source, PriceSource::Oracle);
+        let weights = (plan.current.weight_bps(&kale_token), plan.current.weight_bps(&usdc_token), plan.current.weight_bps(&btc_token));
+        assert_eq!(weights, (3_000, 7_000, 0));
+        assert_eq!(
+            plan.swaps,
+            vec![
+                &env,
+                PlannedSwap {
+                    token_in: usdc_token.clone(),
+                    token_out: btc_token.clone(),
+                    amount_in: 4_000_000_000,
+                    expected_out: 35_959,
+                    min_out: 34_161,
+                },
+            ]
+        );
+        assert_eq!(plan.resulting.balance(&usdc_token), 3_000_000_000);
+        assert_eq!(plan.resulting.balance(&btc_token), 35_959);
+        assert_eq!(plan.resulting.weight_bps(&btc_token), 3_999);
+
+        // Previewing changes nothing; rebalancing carries out the same plan
+        assert_eq!(client.get_pool(&3).balance(&usdc_token), 7_000_000_000);
+        client.rebalance(&user);
+        let pool = client.get_pool(&3);
+        assert_eq!(pool.balance(&kale_token), 25_000_000_000);
+        assert_eq!(pool.balance(&usdc_token), 3_000_000_000);
+        assert_eq!(pool.balance(&btc_token), 35_959);
+
+        let events = env.events().all();
+        assert_eq!(
+            events.slice(events.len() - 2..events.len() - 1),
+            vec![
+                &env,
+                (
+                    client.address.clone(),
+                    (symbol_short!("swap"), user.clone()).into_val(&env),
+                    events::SwapEvent {
+                        token_in: usdc_token.clone(),
+                        token_out: btc_token.clone(),
+                        amount_in: 4_000_000_000,
+                        amount_out: 35_959,
+                        min_out: 34_161,
+                    }
+                    .into_val(&env)
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_preview_rebalance_writes_nothing() {
+        let (env, client, _admin, user, kale_token, usdc_token, btc_token, oracle, _router) = setup_test_environment();
+
+        client.deposit(&user, &25_000_000_000);
+        client.set_risk(&user, &3);
+        client.deposit_asset(&user, &usdc_token, &7_000_000_000);
+
+        // Every preview asks the failing oracle for BTC, which would open the circuit
+        // breaker if previews recorded failures
+        MockOracleClient::new(&env, &oracle).set_price(&Symbol::new(&env, "BTC"), &0);
+        let entries = env.to_ledger_snapshot().ledger_entries;
+        let events = env.events().all().len();
+        for _ in 0..3 {
+            env.ledger().with_mut(|l| l.timestamp += 301);
+            let plan = client.preview_rebalance(&user);
+            assert_eq!(plan.quotes.get(btc_token.clone()).unwrap().source, PriceSource::Fallback);
+            assert_eq!(plan.quotes.get(kale_token.clone()).unwrap().source, PriceSource::Oracle);
+            assert_eq!(plan.swaps.len(), 1);
+        }
+        assert_eq!(env.to_ledger_snapshot().ledger_entries, entries);
+        assert_eq!(env.events().all().len(), events);
+    }
+
+    #[test]
+    fn test_rebalance_trades_only_at_live_prices() {
+        let (env, client, _admin, user, kale_token, usdc_token, btc_token, oracle, _router) = setup_test_environment();
+        setup_mock_router(&env, &client, &kale_token, &usdc_token, &btc_token);
+
+        client.deposit(&user, &25_000_000_000);
+        client.set_risk(&user, &3);
+        client.deposit_asset(&user, &usdc_token, &7_000_000_000);
+
+        // The preview can show the fallback BTC price, but no trade is sized from it
+        MockOracleClient::new(&env, &oracle).set_price(&Symbol::new(&env, "BTC"), &0);
+        env.ledger().with_mut(|l| l.timestamp += 301);
+        assert_eq!(client.preview_rebalance(&user).swaps.len(), 1);
+        assert_eq!(client.try_rebalance(&user), Err(Ok(Error::OracleUnavailable)));
+        assert_eq!(client.get_pool(&3).balance(&usdc_token), 7_000_000_000);
+
+        MockOracleClient::new(&env, &oracle).set_price(&Symbol::new(&env, "BTC"), &111_235_000_000_000);
+        client.rebalance(&user);
+        assert!(client.get_pool(&3).balance(&btc_token) > 0);
+    }
+
+    #[test]
+    fn test_rebalance_skips_failed_swaps() {
+        let (env, client, _admin, user, kale_token, usdc_token, btc_token, _oracle, _router) = setup_test_environment();
+        let router_id = setup_mock_router(&env, &client, &kale_token, &usdc_token, &btc_token);
+
+        client.deposit(&user, &25_000_000_000);
+        client.set_risk(&user, &3);
+        client.deposit_asset(&user, &usdc_token, &7_000_000_000);
+
+        // The router now quotes BTC at twice the oracle price, below the minimum output
+        MockRouterContractClient::new(&env, &router_id).set_price(&btc_token, &222_470_000_000_000);
+        client.rebalance(&user);
+        let pool = client.get_pool(&3);
+        assert_eq!(pool.balance(&usdc_token), 7_000_000_000);
+        assert_eq!(pool.balance(&btc_token), 0);
+
+        let events = env.events().all();
+        assert_eq!(
+            events.slice(events.len() - 2..events.len() - 1),
+            vec![
+                &env,
+                (
+                    client.address.clone(),
+                    (symbol_short!("swap"), user.clone(), symbol_short!("failed")).into_val(&env),
+                    events::SwapEvent {
+                        token_in: usdc_token.clone(),
+                        token_out: btc_token.clone(),
+                        amount_in: 4_000_000_000,
+                        amount_out: 0,
+                        min_out: 34_161,
+                    }
+                    .into_val(&env)
+                ),
+            ]
+        );
+
+        // Pools claiming more than the fund holds block rebalancing
+        client.set_drift_band(&10_000);
+        token::Client::new(&env, &usdc_token).burn(&client.address, &10_004_000_000_000);
+        assert_eq!(client.try_rebalance(&user), Err(Ok(Error::BalanceMismatch)));
+    }
+
+    #[test]
+    fn test_check_solvency_and_pause_on_shortfall() {
+        let (env, client, _admin, user, kale_token, usdc_token, _btc_token, _oracle, _router) = setup_test_environment();
+
+        client.deposit(&user, &500_000_000_000);
+        client.deposit_asset(&user, &usdc_token, &1_000_000_000);
+        client.withdraw_partial(&user, &kale_token, &200_000_000_000);
+        let liabilities = client.get_liabilities();
+        assert_eq!(liabilities.get(kale_token.clone()), Some(300_000_000_000));
+        assert_eq!(liabilities.get(usdc_token.clone()), Some(1_000_000_000));
+
+        let report = client.check_solvency();
+        assert_eq!(report.len(), 3);
+        assert_eq!(
+            report.iter().find(|solvency| solvency.asset == kale_token).unwrap(),
+            Solvency { asset: kale_token.clone(), liabilities: 300_000_000_000, holdings: 10_300_000_000_000 }
+        );
+        assert!(!client.pause_if_insolvent());
+
+        // Tokens leaving the fund behind the pools' backs leave USDC short
+        token::Client::new(&env, &usdc_token).burn(&client.address, &10_000_500_000_000);
+        let usdc = client.check_solvency().iter().find(|solvency| solvency.asset == usdc_token).unwrap();
+        assert_eq!(usdc.shortfall(), 500_000_000);
+        assert!(client.pause_if_insolvent());
+        assert_eq!(client.try_deposit(&user, &1), Err(Ok(Error::Paused)));
+        assert_eq!(client.try_rebalance(&user), Err(Ok(Error::Paused)));
+
+        // Withdrawals stay open while paused
+        client.withdraw_partial(&user, &kale_token, &100_000_000_000);
+        client.unpause(&Operation::Deposits);
+        client.deposit(&user, &1);
     }
 
-    // Get user portfolio
-    pub fn get_portfolio(env: Env, user: Address) -> Portfolio {
-        let portfolio_key = DataKey::Portfolio(user.clone());
-        env.storage().persistent().get(&portfolio_key)
-            .unwrap_or(Portfolio {
-                user: user.clone(),
-                kale_balance: 0,
-                usdc_balance: 0,
-                btc_balance: 0,
-                risk_level: 1,
-            })
+    #[test]
+    fn test_guardian_pauses_operations_independently() {
+        let (env, client, _admin, user, kale_token, usdc_token, btc_token, _oracle, _router) = setup_test_environment();
+        setup_mock_router(&env, &client, &kale_token, &usdc_token, &btc_token);
+        client.deposit(&user, &500_000_000_000);
+
+        assert_eq!(client.try_pause(&Operation::Deposits), Err(Ok(Error::Unauthorized)));
+        let guardian = Address::generate(&env);
+        client.set_guardian(&guardian);
+
+        client.pause(&Operation::Deposits);
+        assert_eq!(env.auths()[0].0, guardian);
+        assert_eq!(
+            env.events().all().slice(env.events().all().len() - 1..),
+            vec![
+                &env,
+                (client.address.clone(), (symbol_short!("pause"), Operation::Deposits).into_val(&env), true.into_val(&env)),
+            ]
+        );
+        assert_eq!(client.try_deposit(&user, &1), Err(Ok(Error::Paused)));
+        assert_eq!(client.try_deposit_asset(&user, &usdc_token, &1), Err(Ok(Error::Paused)));
+
+        // Rebalances need swaps here, so pausing swaps stops them too
+        client.pause(&Operation::Swaps);
+        assert!(!client.is_paused(&Operation::Rebalances));
+        assert_eq!(client.try_rebalance(&user), Err(Ok(Error::Paused)));
+
+        // Withdrawals stay open
+        client.withdraw_partial(&user, &kale_token, &100_000_000_000);
+
+        client.unpause(&Operation::Swaps);
+        client.rebalance(&user);
+        assert_eq!(client.try_deposit(&user, &1), Err(Ok(Error::Paused)));
+        client.unpause(&Operation::Deposits);
+        client.deposit(&user, &1);
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use soroban_sdk::{testutils::Address as _, Address, Env, Symbol};
-    use soroban_sdk::token;
+    // Release build of the fund at storage version 2, with fixed KALE, USDC and BTC
+    // slots, made from the commit that added `upgrade` (62cd315). That build still exported
+    // the mock oracle, whose reflector types its spec leaves out.
+    mod fund_v2 {
+        use crate::reflector::{Asset, PriceData};
+        soroban_sdk::contractimport!(file = "tests/fixtures/kale_pension_fund_v2.wasm");
+    }
 
-    fn setup_test_environment() -> (Env, KalePensionFundClient<'static>, Address, Address, Address, Address, Address, Address, Address) {
+    #[test]
+    fn test_upgrade_replaces_code_and_keeps_storage() {
         let env = Env::default();
         env.mock_all_auths();
-
-        let contract_id = env.register_contract(None, KalePensionFund);
-        let client = KalePensionFundClient::new(&env, &contract_id);
-
+        env.budget().reset_unlimited();
         let admin = Address::generate(&env);
         let user = Address::generate(&env);
-        
-        // Create proper mock token contracts
         let kale_token = env.register_stellar_asset_contract_v2(admin.clone()).address();
         let usdc_token = env.register_stellar_asset_contract_v2(admin.clone()).address();
         let btc_token = env.register_stellar_asset_contract_v2(admin.clone()).address();
-        
-        // Create mock oracle contract for testing
-        let mock_oracle_id = env.register_contract(None, MockOracleContract);
-        let mock_oracle_client = MockOracleClient::new(&env, &mock_oracle_id);
-        
-        // Set up initial oracle prices with current market values
-        mock_oracle_client.set_price(&Symbol::new(&env, "KALE"), &120_000_000); // $0.12
-        mock_oracle_client.set_price(&Symbol::new(&env, "BTC"), &111235_000_000_000); // ~$111,235 (current market price)
-        mock_oracle_client.set_price(&Symbol::new(&env, "USDC"), &1_000_000_000); // $1.00
-        
-        // Create router address (not needed for price testing but required for initialization)
-        let router = Address::generate(&env);
+        mint_tokens_to_user(&env, &user, &kale_token, &usdc_token, &btc_token);
+        let oracle = env.register_contract(None, MockOracleContract);
+        MockOracleClient::new(&env, &oracle).set_price(&Symbol::new(&env, "KALE"), &120_000_000);
+        MockOracleClient::new(&env, &oracle).set_price(&Symbol::new(&env, "USDC"), &1_000_000_000);
+        MockOracleClient::new(&env, &oracle).set_price(&Symbol::new(&env, "BTC"), &111_235_000_000_000);
+
+        // State saved by the old build through its own entrypoints
+        let fund = env.register_contract_wasm(None, fund_v2::WASM);
+        let old = fund_v2::Client::new(&env, &fund);
+        old.initialize(&admin, &kale_token, &usdc_token, &btc_token, &oracle, &Address::generate(&env));
+        old.deposit(&user, &500_000_000_000);
+        old.deposit_asset(&user, &usdc_token, &1_000_000_000);
+        old.set_payout_schedule(&user, &usdc_token, &fund_v2::PayoutAmount::Fixed(1_000_000), &86_400);
+        let old_portfolio = old.get_portfolio(&user);
+        let old_pool = old.get_pool(&old_portfolio.risk_level);
+        assert_eq!(old.get_storage_version(), 2);
+
+        // Tests run the current build natively: the host hands a contract whose code is
+        // the empty wasm to the implementation registered at its address
+        let current = env.deployer().upload_contract_wasm(Bytes::new(&env));
+        old.upgrade(&current);
+        assert_eq!(env.auths()[0].0, admin);
+        env.register_contract(&fund, KalePensionFund);
+        let client = KalePensionFundClient::new(&env, &fund);
+
+        assert_eq!(client.migrate(&vec![&env, user.clone()]), 1);
+        assert_eq!(client.get_storage_version(), STORAGE_VERSION);
+        assert_eq!(client.get_admin(), Some(admin));
 
-        // Initialize contract with mock oracle
-        client.initialize(&admin, &kale_token, &usdc_token, &btc_token, &mock_oracle_id, &router);
-        
-        // Store mock oracle address for testing (using contract context)
-        env.as_contract(&contract_id, || {
-            env.storage().instance().set(&DataKey::MockOracleAddress, &mock_oracle_id);
-        });
+        let portfolio = client.get_portfolio(&user);
+        assert_eq!(portfolio.risk_level, old_portfolio.risk_level);
+        assert_eq!(portfolio.shares, old_portfolio.shares);
+        assert_eq!(portfolio.own_contributions, old_portfolio.own_contributions);
+        assert_eq!(portfolio.balance(&kale_token), old_portfolio.kale_balance);
+        assert_eq!(portfolio.balance(&usdc_token), old_portfolio.usdc_balance);
+        let pool = client.get_pool(&old_portfolio.risk_level);
+        assert_eq!(pool.total_shares, old_pool.total_shares);
+        assert_eq!(pool.balance(&kale_token), old_pool.kale_balance);
+        assert_eq!(pool.balance(&usdc_token), old_pool.usdc_balance);
+        assert_eq!(pool.balance(&btc_token), old_pool.btc_balance);
+        assert_eq!(client.get_liabilities().get(kale_token.clone()), Some(500_000_000_000));
+        assert_eq!(client.get_payout_schedule(&user).unwrap().asset, usdc_token);
+        assert!(client.check_solvency().iter().all(|solvency| solvency.shortfall() == 0));
+    }
 
-        // Setup initial token balances for testing
-        let kale_client = token::StellarAssetClient::new(&env, &kale_token);
-        let usdc_client = token::StellarAssetClient::new(&env, &usdc_token);
-        let btc_client = token::StellarAssetClient::new(&env, &btc_token);
+    #[test]
+    fn test_migrate_converts_legacy_portfolios() {
+        let (env, client, _admin, user, kale_token, usdc_token, btc_token, _oracle, _router) = setup_test_environment();
+        let member = Address::generate(&env);
+        mint_tokens_to_user(&env, &member, &kale_token, &usdc_token, &btc_token);
+        client.deposit(&member, &100_000_000_000);
+
+        // A member saved by the version 1 layout, with balances held directly
+        env.as_contract(&client.address, || {
+            env.storage().instance().remove(&DataKey::StorageVersion);
+            let legacy = legacy::PortfolioV1 {
+                user: user.clone(),
+                kale_balance: 500_000_000_000,
+                usdc_balance: 1_000_000_000,
+                btc_balance: 0,
+                risk_level: 2,
+            };
+            env.storage().persistent().set(&DataKey::Portfolio(user.clone()), &legacy);
+        });
+        assert_eq!(client.get_storage_version(), 1);
 
-        // Mint tokens to user for testing
-        kale_client.mint(&user, &10000_000_000_000); // 10,000 KALE
-        usdc_client.mint(&user, &10000_000_000_000); // 10,000 USDC
-        btc_client.mint(&user, &100_000_000_000);    // 100 BTC
-        
-        // Mint tokens to contract for withdrawal testing
-        kale_client.mint(&contract_id, &10000_000_000_000);
-        usdc_client.mint(&contract_id, &10000_000_000_000);
-        btc_client.mint(&contract_id, &100_000_000_000);
+        assert_eq!(client.migrate(&vec![&env, user.clone(), member.clone()]), 1);
+        assert_eq!(client.get_storage_version(), STORAGE_VERSION);
 
-        (env, client, admin, user, kale_token, usdc_token, btc_token, mock_oracle_id, router)
+        // $6,000 of KALE plus $100 of USDC, now as shares of the moderate pool
+        let portfolio = client.get_portfolio(&user);
+        assert_eq!(portfolio.risk_level, 2);
+        assert_eq!(portfolio.shares, 61_000_000_000);
+        assert_eq!(portfolio.balance(&kale_token), 500_000_000_000);
+        assert_eq!(portfolio.balance(&usdc_token), 1_000_000_000);
+        assert_eq!(portfolio.own_contributions, 61_000_000_000);
+        assert_eq!(client.get_liabilities().get(kale_token.clone()), Some(600_000_000_000));
+
+        // Converted and current portfolios are left alone
+        assert_eq!(client.migrate(&vec![&env, user.clone(), member.clone()]), 0);
+        assert_eq!(client.get_portfolio(&member).balance(&kale_token), 100_000_000_000);
     }
 
-    fn mint_tokens_to_user(env: &Env, user: &Address, kale_token: &Address, usdc_token: &Address, btc_token: &Address) {
-        let kale_client = token::StellarAssetClient::new(env, kale_token);
-        let usdc_client = token::StellarAssetClient::new(env, usdc_token);
-        let btc_client = token::StellarAssetClient::new(env, btc_token);
+    #[test]
+    fn test_migrate_rejects_unbacked_legacy_balances() {
+        let (env, client, _admin, user, kale_token, _usdc_token, _btc_token, _oracle, _router) = setup_test_environment();
+
+        // A version 1 record claiming more KALE than the fund holds
+        let holdings = token::Client::new(&env, &kale_token).balance(&client.address);
+        env.as_contract(&client.address, || {
+            env.storage().instance().remove(&DataKey::StorageVersion);
+            let legacy = legacy::PortfolioV1 {
+                user: user.clone(),
+                kale_balance: holdings + 1,
+                usdc_balance: 0,
+                btc_balance: 0,
+                risk_level: 1,
+            };
+            env.storage().persistent().set(&DataKey::Portfolio(user.clone()), &legacy);
+        });
 
-        kale_client.mint(user, &10000_000_000_000); // 10,000 KALE
-        usdc_client.mint(user, &10000_000_000_000); // 10,000 USDC
-        btc_client.mint(user, &100_000_000_000);    // 100 BTC
+        assert_eq!(client.try_migrate(&vec![&env, user.clone()]), Err(Ok(Error::BalanceMismatch)));
+        assert_eq!(client.get_storage_version(), 1);
+        assert_eq!(client.get_pool(&1).total_shares, 0);
     }
 
     #[test]
-    fn test_initialize() {
-        let (env, client, admin, _user, kale_token, usdc_token, btc_token, oracle, router) = setup_test_environment();
-
-        // Verify initialization was successful by checking we can get the portfolio (which requires tokens to be set)
-        let user = Address::generate(&env);
+    fn test_migrate_converts_fixed_asset_slots() {
+        let (env, client, _admin, user, kale_token, usdc_token, btc_token, _oracle, _router) = setup_test_environment();
+        client.deposit(&user, &100_000_000_000);
+        client.set_payout_schedule(&user, &usdc_token, &PayoutAmount::Fixed(1_000_000), &86_400);
+        let pool = client.get_pool(&1);
         let portfolio = client.get_portfolio(&user);
-        assert_eq!(portfolio.kale_balance, 0);
-        assert_eq!(portfolio.risk_level, 1);
+
+        // The same fund as saved by the version 2 layout, with fixed KALE, USDC and BTC slots
+        env.as_contract(&client.address, || {
+            let instance = env.storage().instance();
+            instance.remove(&DataKey::Assets);
+            instance.set(&legacy::DataKeyV2::UsdcTokenAddress, &usdc_token);
+            instance.set(&legacy::DataKeyV2::BtcTokenAddress, &btc_token);
+            instance.set(&DataKey::StorageVersion, &2u32);
+            let profile = |name: &str, kale_bps: u32, usdc_bps: u32, btc_bps: u32| legacy::RiskProfileV2 {
+                name: String::from_str(&env, name),
+                kale_bps,
+                usdc_bps,
+                btc_bps,
+            };
+            let mut profiles = Map::new(&env);
+            profiles.set(1u32, profile("Conservative", 1_000, 7_000, 2_000));
+            profiles.set(4u32, profile("Growth", 5_000, 2_000, 3_000));
+            instance.set(&DataKey::RiskProfiles, &profiles);
+            instance.set(&DataKey::Liabilities, &legacy::LiabilitiesV2 {
+                kale_balance: 100_000_000_000,
+                usdc_balance: 0,
+                btc_balance: 0,
+            });
+
+            let persistent = env.storage().persistent();
+            persistent.set(&DataKey::Pool(1), &legacy::PoolV2 {
+                kale_balance: 100_000_000_000,
+                usdc_balance: 0,
+                btc_balance: 0,
+                total_shares: pool.total_shares,
+                fee_shares: 0,
+                fees_accrued_at: 0,
+                high_water_mark: pool.high_water_mark,
+            });
+            persistent.set(&DataKey::Portfolio(user.clone()), &legacy::PortfolioV2 {
+                user: user.clone(),
+                shares: portfolio.shares,
+                kale_balance: 100_000_000_000,
+                usdc_balance: 0,
+                btc_balance: 0,
+                risk_level: 1,
+                retirement_date: portfolio.retirement_date,
+                hardship_approved: false,
+                last_activity: portfolio.last_activity,
+                own_contributions: portfolio.own_contributions,
+                employer_shares: 0,
+                vested_bps: 0,
+            });
+            persistent.set(&DataKey::PayoutSchedule(user.clone()), &legacy::PayoutScheduleV2 {
+                asset: legacy::TokenKind::Usdc,
+                amount: PayoutAmount::Fixed(1_000_000),
+                interval: 86_400,
+                next_payout: 0,
+            });
+        });
+
+        assert_eq!(client.migrate(&vec![&env, user.clone()]), 1);
+        assert_eq!(client.get_storage_version(), 3);
+
+        let assets = client.get_assets();
+        assert_eq!(assets.len(), 3);
+        assert_eq!(assets.get(btc_token.clone()).unwrap().price_pair, Symbol::new(&env, "BTC_USD"));
+        let profiles = client.get_risk_profiles();
+        assert_eq!(profiles.get(4).unwrap().target_bps(&kale_token), 5_000);
+        assert_eq!(profiles.get(1).unwrap().target_bps(&usdc_token), 7_000);
+        assert_eq!(client.get_pool(&1).balance(&kale_token), 100_000_000_000);
+        assert_eq!(client.get_pool(&1).total_shares, pool.total_shares);
+        assert_eq!(client.get_liabilities().get(kale_token.clone()), Some(100_000_000_000));
+        assert_eq!(client.get_portfolio(&user).balance(&kale_token), 100_000_000_000);
+        assert_eq!(client.get_payout_schedule(&user).unwrap().asset, usdc_token);
+        assert!(client.check_solvency().iter().all(|solvency| solvency.shortfall() == 0));
     }
 
     #[test]
-    fn test_get_empty_portfolio() {
+    fn test_rebalance_without_portfolio() {
         let (_env, client, _admin, user, _kale_token, _usdc_token, _btc_token, _oracle, _router) = setup_test_environment();
 
-        let portfolio = client.get_portfolio(&user);
-        assert_eq!(portfolio.user, user);
-        assert_eq!(portfolio.kale_balance, 0);
-        assert_eq!(portfolio.usdc_balance, 0);
-        assert_eq!(portfolio.btc_balance, 0);
-        assert_eq!(portfolio.risk_level, 1);
+        let result = client.try_rebalance(&user);
+        assert_eq!(result, Err(Ok(Error::PortfolioNotFound)));
     }
 
     #[test]
-    fn test_deposit_kale() {
-        let (env, client, admin, user, kale_token, _usdc_token, _btc_token, _oracle, _router) = setup_test_environment();
+    fn test_withdraw() {
+        let (_env, client, _admin, user, kale_token, usdc_token, btc_token, _oracle, _router) = setup_test_environment();
 
-        // Mock the token transfer (in real tests we would need actual tokens)
-        // For unit tests, we focus on the contract logic
-        
-        // Deposit KALE (the transfer is mocked)
-        client.deposit(&user, &500_000_000_000); // 500 KALE
+        // Deposit KALE
+        client.deposit(&user, &500_000_000_000);
 
-        // Check portfolio
+        // Verify portfolio has balance
+        let portfolio_before = client.get_portfolio(&user);
+        assert_eq!(portfolio_before.balance(&kale_token), 500_000_000_000);
+
+        // Withdraw (token transfers are mocked in tests)
+        client.withdraw(&user);
+
+        // Portfolio should be empty
         let portfolio = client.get_portfolio(&user);
-        assert_eq!(portfolio.kale_balance, 500_000_000_000);
-        assert_eq!(portfolio.risk_level, 1); // Default conservative
+        assert_eq!(portfolio.balance(&kale_token), 0);
+        assert_eq!(portfolio.balance(&usdc_token), 0);
+        assert_eq!(portfolio.balance(&btc_token), 0);
+        assert_eq!(portfolio.risk_level, 1); // Default for new portfolio
     }
 
     #[test]
-    fn test_deposit_negative_amount() {
-        let (_env, client, _admin, user, _kale_token, _usdc_token, _btc_token, _oracle, _router) = setup_test_environment();
+    fn test_withdraw_partial() {
+        let (env, client, _admin, user, kale_token, usdc_token, _btc_token, _oracle, _router) = setup_test_environment();
 
-        // Should handle gracefully without panicking
-        client.deposit(&user, &-100);
-        
-        // Verify no portfolio was created
+        client.deposit(&user, &500_000_000_000);
+        client.set_risk(&user, &3);
+
+        client.withdraw_partial(&user, &kale_token, &200_000_000_000);
         let portfolio = client.get_portfolio(&user);
-        assert_eq!(portfolio.kale_balance, 0);
+        assert_eq!(portfolio.balance(&kale_token), 300_000_000_000);
+        assert_eq!(portfolio.risk_level, 3); // Portfolio settings are kept
+
+        let kale = token::Client::new(&env, &kale_token);
+        assert_eq!(kale.balance(&user), 10_000_000_000_000 - 300_000_000_000);
+
+        let result = client.try_withdraw_partial(&user, &kale_token, &300_000_000_001);
+        assert_eq!(result, Err(Ok(Error::InsufficientBalance)));
+
+        let result = client.try_withdraw_partial(&user, &usdc_token, &1);
+        assert_eq!(result, Err(Ok(Error::InsufficientBalance)));
     }
 
     #[test]
-    fn test_deposit_zero_amount() {
-        let (_env, client, _admin, user, _kale_token, _usdc_token, _btc_token, _oracle, _router) = setup_test_environment();
-
-        // Should handle gracefully without panicking
-        client.deposit(&user, &0);
-        
-        // Verify no portfolio was created
-        let portfolio = client.get_portfolio(&user);
-        assert_eq!(portfolio.kale_balance, 0);
+    fn test_withdraw_partial_limited_to_own_slice() {
+        let (env, client, _admin, user, kale_token, usdc_token, btc_token, _oracle, _router) = setup_test_environment();
+        let saver = Address::generate(&env);
+        mint_tokens_to_user(&env, &saver, &kale_token, &usdc_token, &btc_token);
+
+        // $60 of KALE and $1,000 of USDC in the same pool: the member owns 60/1060
+        // of the USDC, about 56.6 USDC, though their shares are worth $60
+        client.deposit(&user, &5_000_000_000);
+        client.deposit_asset(&saver, &usdc_token, &10_000_000_000);
+        assert_eq!(client.get_portfolio(&user).balance(&usdc_token), 566_037_735);
+
+        let result = client.try_withdraw_partial(&user, &usdc_token, &570_000_000);
+        assert_eq!(result, Err(Ok(Error::InsufficientBalance)));
+        client.withdraw_partial(&user, &usdc_token, &566_037_735);
+        assert_eq!(token::Client::new(&env, &usdc_token).balance(&user), 10_000_566_037_735);
     }
 
     #[test]
-    fn test_multiple_deposits() {
-        let (env, client, admin, user, kale_token, _usdc_token, _btc_token, _oracle, _router) = setup_test_environment();
+    fn test_withdraw_value_pro_rata() {
+        let (_env, client, _admin, user, kale_token, usdc_token, _btc_token, _oracle, _router) = setup_test_environment();
 
-        // First deposit
-        client.deposit(&user, &300_000_000_000);
-        let portfolio = client.get_portfolio(&user);
-        assert_eq!(portfolio.kale_balance, 300_000_000_000);
+        // $120 of KALE (1000 KALE at $0.12) and $120 of USDC
+        client.deposit(&user, &10_000_000_000);
+        client.deposit_asset(&user, &usdc_token, &1_200_000_000);
 
-        // Second deposit
-        client.deposit(&user, &200_000_000_000);
+        // Withdraw a quarter of the $240 total value
+        client.withdraw_value(&user, &600_000_000);
         let portfolio = client.get_portfolio(&user);
-        assert_eq!(portfolio.kale_balance, 500_000_000_000);
+        assert_eq!(portfolio.balance(&kale_token), 7_500_000_000);
+        assert_eq!(portfolio.balance(&usdc_token), 900_000_000);
+
+        let result = client.try_withdraw_value(&user, &1_800_000_001);
+        assert_eq!(result, Err(Ok(Error::InsufficientBalance)));
+
+        let result = client.try_withdraw_value(&user, &0);
+        assert_eq!(result, Err(Ok(Error::InvalidAmount)));
     }
 
     #[test]
-    fn test_set_risk_levels() {
-        let (env, client, admin, user, kale_token, _usdc_token, _btc_token, _oracle, _router) = setup_test_environment();
+    fn test_retirement_lock_blocks_withdrawals() {
+        let (env, client, _admin, user, kale_token, _usdc_token, _btc_token, _oracle, _router) = setup_test_environment();
 
-        // Deposit KALE first
-        client.deposit(&user, &500_000_000_000);
+        let reserve = Address::generate(&env);
+        client.set_retirement_policy(&1_000, &1_000, &reserve);
 
-        // Test conservative (1)
-        client.set_risk(&user, &1);
-        let portfolio = client.get_portfolio(&user);
-        assert_eq!(portfolio.risk_level, 1);
+        // First deposit enrolls with the earliest allowed retirement date
+        client.deposit(&user, &500_000_000_000);
+        assert_eq!(client.get_portfolio(&user).retirement_date, 1_000);
 
-        // Test moderate (2)
-        client.set_risk(&user, &2);
-        let portfolio = client.get_portfolio(&user);
-        assert_eq!(portfolio.risk_level, 2);
+        assert_eq!(client.try_withdraw(&user), Err(Ok(Error::RetirementLocked)));
+        assert_eq!(
+            client.try_withdraw_partial(&user, &kale_token, &1),
+            Err(Ok(Error::RetirementLocked))
+        );
+        assert_eq!(client.try_withdraw_value(&user, &1), Err(Ok(Error::RetirementLocked)));
 
-        // Test aggressive (3)
-        client.set_risk(&user, &3);
-        let portfolio = client.get_portfolio(&user);
-        assert_eq!(portfolio.risk_level, 3);
+        env.ledger().with_mut(|l| l.timestamp = 1_000);
+        client.withdraw(&user);
+        assert_eq!(client.get_portfolio(&user).balance(&kale_token), 0);
     }
 
     #[test]
-    fn test_invalid_risk_level_zero() {
-        let (env, client, admin, user, kale_token, _usdc_token, _btc_token, _oracle, _router) = setup_test_environment();
+    fn test_enroll_and_extend_retirement() {
+        let (env, client, _admin, user, _kale_token, _usdc_token, _btc_token, _oracle, _router) = setup_test_environment();
 
-        // Deposit KALE first
-        client.deposit(&user, &500_000_000_000);
-        let portfolio_before = client.get_portfolio(&user);
-        let initial_risk_level = portfolio_before.risk_level;
+        let reserve = Address::generate(&env);
+        client.set_retirement_policy(&1_000, &0, &reserve);
 
-        // Should handle gracefully without panicking
-        client.set_risk(&user, &0);
-        
-        // Verify risk level wasn't changed
-        let portfolio_after = client.get_portfolio(&user);
-        assert_eq!(portfolio_after.risk_level, initial_risk_level);
+        assert_eq!(client.try_enroll(&user, &999), Err(Ok(Error::InvalidRetirementDate)));
+
+        client.enroll(&user, &5_000);
+        assert_eq!(client.get_portfolio(&user).retirement_date, 5_000);
+        assert_eq!(client.try_enroll(&user, &6_000), Err(Ok(Error::AlreadyEnrolled)));
+
+        // Retirement can only move later
+        assert_eq!(client.try_extend_retirement(&user, &4_000), Err(Ok(Error::InvalidRetirementDate)));
+        client.extend_retirement(&user, &8_000);
+        assert_eq!(client.get_portfolio(&user).retirement_date, 8_000);
+
+        // Deposits keep the chosen date
+        client.deposit(&user, &100_000_000_000);
+        assert_eq!(client.get_portfolio(&user).retirement_date, 8_000);
     }
 
     #[test]
-    fn test_invalid_risk_level_four() {
-        let (env, client, admin, user, kale_token, _usdc_token, _btc_token, _oracle, _router) = setup_test_environment();
+    fn test_early_withdraw_penalty_and_hardship() {
+        let (env, client, _admin, user, kale_token, usdc_token, btc_token, _oracle, _router) = setup_test_environment();
 
-        // Deposit KALE first
+        let reserve = Address::generate(&env);
+        assert_eq!(
+            client.try_set_retirement_policy(&1_000, &10_001, &reserve),
+            Err(Ok(Error::InvalidConfig))
+        );
+        client.set_retirement_policy(&1_000, &1_000, &reserve); // 10% penalty
+
+        let kale = token::Client::new(&env, &kale_token);
         client.deposit(&user, &500_000_000_000);
-        let portfolio_before = client.get_portfolio(&user);
-        let initial_risk_level = portfolio_before.risk_level;
+        client.early_withdraw(&user);
 
-        // Should handle gracefully without panicking
-        client.set_risk(&user, &4);
-        
-        // Verify risk level wasn't changed
-        let portfolio_after = client.get_portfolio(&user);
-        assert_eq!(portfolio_after.risk_level, initial_risk_level);
-    }
+        assert_eq!(kale.balance(&reserve), 50_000_000_000);
+        assert_eq!(kale.balance(&user), 10_000_000_000_000 - 50_000_000_000);
+        assert_eq!(client.get_portfolio(&user).balance(&kale_token), 0);
 
-    #[test]
-    #[should_panic(expected = "Portfolio not found")]
-    fn test_set_risk_without_portfolio() {
-        let (_env, client, _admin, user, _kale_token, _usdc_token, _btc_token, _oracle, _router) = setup_test_environment();
+        // Hardship approval waives the penalty
+        let member = Address::generate(&env);
+        mint_tokens_to_user(&env, &member, &kale_token, &usdc_token, &btc_token);
+        client.deposit(&member, &500_000_000_000);
+        client.approve_hardship(&member);
+        client.early_withdraw(&member);
 
-        client.set_risk(&user, &2);
+        assert_eq!(kale.balance(&member), 10_000_000_000_000);
+        assert_eq!(kale.balance(&reserve), 50_000_000_000);
     }
 
     #[test]
-    fn test_get_price() {
-        let (_env, client, _admin, _user, _kale_token, _usdc_token, _btc_token, _oracle, _router) = setup_test_environment();
+    fn test_early_withdraw_requires_policy() {
+        let (env, client, _admin, user, kale_token, _usdc_token, _btc_token, _oracle, _router) = setup_test_environment();
 
-        // Test KALE_USD price
-        let kale_price = client.get_price(&Symbol::new(&_env, "KALE_USD"));
-        assert_eq!(kale_price, 120_000_000); // 0.12 USDC
+        client.enroll(&user, &5_000);
+        client.deposit(&user, &500_000_000_000);
+        assert_eq!(client.try_early_withdraw(&user), Err(Ok(Error::PolicyNotSet)));
+        assert_eq!(client.get_portfolio(&user).balance(&kale_token), 500_000_000_000);
 
-        // Test BTC_USD price
-        let btc_price = client.get_price(&Symbol::new(&_env, "BTC_USD"));
-        assert_eq!(btc_price, 111235_000_000_000); // ~$111,235 (current market price)
+        let reserve = Address::generate(&env);
+        client.set_retirement_policy(&0, &1_000, &reserve);
+        client.early_withdraw(&user);
+        assert_eq!(token::Client::new(&env, &kale_token).balance(&reserve), 50_000_000_000);
     }
 
     #[test]
-    fn test_get_price_unsupported_pair() {
-        let (_env, client, _admin, _user, _kale_token, _usdc_token, _btc_token, _oracle, _router) = setup_test_environment();
+    fn test_claim_fixed_payouts() {
+        let (env, client, _admin, user, _kale_token, usdc_token, _btc_token, _oracle, _router) = setup_test_environment();
+
+        client.deposit_asset(&user, &usdc_token, &1_000_000_000_000);
+        client.set_payout_schedule(&user, &usdc_token, &PayoutAmount::Fixed(100_000_000_000), &2_592_000);
+
+        // First installment is due immediately
+        assert_eq!(client.claim_payout(&user), 100_000_000_000);
+        assert_eq!(client.try_claim_payout(&user), Err(Ok(Error::PayoutNotDue)));
+
+        // Two more intervals accrue two installments
+        env.ledger().with_mut(|l| l.timestamp += 2 * 2_592_000);
+        assert_eq!(client.claim_payout(&user), 200_000_000_000);
+        assert_eq!(client.get_portfolio(&user).balance(&usdc_token), 700_000_000_000);
+
+        let usdc = token::Client::new(&env, &usdc_token);
+        assert_eq!(usdc.balance(&user), 10_000_000_000_000 - 700_000_000_000);
 
-        // Should handle gracefully without panicking - returns fallback price
-        let price = client.get_price(&Symbol::new(&_env, "ETH_USD"));
-        assert_eq!(price, 1_000_000_000); // $1.00 fallback price
+        client.cancel_payout_schedule(&user);
+        assert_eq!(client.try_claim_payout(&user), Err(Ok(Error::NoPayoutSchedule)));
     }
 
     #[test]
-    fn test_demo_price_guess() {
-        let (_env, client, _admin, _user, _kale_token, _usdc_token, _btc_token, _oracle, _router) = setup_test_environment();
+    fn test_claim_payout_requires_retirement() {
+        let (env, client, _admin, user, _kale_token, usdc_token, _btc_token, _oracle, _router) = setup_test_environment();
 
-        // Test BTC price guess (should always return true in our demo since we simulate +1% increase)
-        let result = client.demo_price_guess(&Symbol::new(&_env, "BTC_USD"), &true);
-        assert_eq!(result, true); // Should be correct since we guess "will rise" and simulate +1%
+        let reserve = Address::generate(&env);
+        client.set_retirement_policy(&1_000, &0, &reserve);
+        client.deposit_asset(&user, &usdc_token, &1_000_000_000_000);
 
-        // Test guessing it will fall (should be false since we simulate +1% increase)
-        let result = client.demo_price_guess(&Symbol::new(&_env, "BTC_USD"), &false);
-        assert_eq!(result, false); // Should be incorrect since we guess "will fall" but simulate +1%
+        assert_eq!(
+            client.try_set_payout_schedule(&user, &usdc_token, &PayoutAmount::PercentBps(100), &86_400),
+            Err(Ok(Error::RetirementLocked))
+        );
     }
 
     #[test]
-    fn test_debug_contract_status() {
-        let (_env, client, _admin, _user, kale_token, usdc_token, btc_token, _oracle, _router) = setup_test_environment();
+    fn test_claim_payout_converts_from_kale() {
+        let (env, client, _admin, user, kale_token, usdc_token, btc_token, _oracle, _router) = setup_test_environment();
+        setup_mock_router(&env, &client, &kale_token, &usdc_token, &btc_token);
 
-        // Test contract debug status
-        let (is_initialized, kale_addr, usdc_addr, btc_addr) = client.debug_contract_status();
+        // 1000 KALE at $0.12 = $120, paid out as 1% per installment in USDC
+        client.deposit(&user, &10_000_000_000);
+        client.set_payout_schedule(&user, &usdc_token, &PayoutAmount::PercentBps(100), &86_400);
 
-        assert_eq!(is_initialized, true);
-        assert_eq!(kale_addr, Some(kale_token));
-        assert_eq!(usdc_addr, Some(usdc_token));
-        assert_eq!(btc_addr, Some(btc_token));
+        assert_eq!(client.claim_payout(&user), 12_000_000);
+        let portfolio = client.get_portfolio(&user);
+        assert_eq!(portfolio.balance(&kale_token), 10_000_000_000 - 100_000_000);
+        assert_eq!(portfolio.balance(&usdc_token), 0);
     }
 
     #[test]
-    fn test_rebalance_no_change_needed() {
-        let (env, client, admin, user, kale_token, _usdc_token, _btc_token, _oracle, _router) = setup_test_environment();
+    fn test_claim_payout_slippage_stays_with_member() {
+        let (env, client, _admin, user, kale_token, usdc_token, btc_token, _oracle, _router) = setup_test_environment();
+        let router_id = setup_mock_router(&env, &client, &kale_token, &usdc_token, &btc_token);
+        let saver = Address::generate(&env);
+        mint_tokens_to_user(&env, &saver, &kale_token, &usdc_token, &btc_token);
+
+        // $120 of KALE each; the router fills KALE at $0.116, inside the 5% slippage floor
+        client.deposit(&user, &10_000_000_000);
+        client.deposit(&saver, &10_000_000_000);
+        MockRouterContractClient::new(&env, &router_id).set_price(&kale_token, &116_000_000);
+        client.set_payout_schedule(&user, &usdc_token, &PayoutAmount::PercentBps(1_000), &86_400);
+
+        // 100 KALE ($12) sold for 11.6 USDC, and $12 of the member's shares burned for it
+        assert_eq!(client.claim_payout(&user), 116_000_000);
+        assert_eq!(client.get_portfolio(&user).shares, 1_080_000_000);
+        assert_eq!(client.get_portfolio(&saver).balance(&kale_token), 10_000_000_000);
+    }
 
-        // Deposit KALE
+    #[test]
+    fn test_set_beneficiaries_validation() {
+        let (env, client, _admin, user, _kale_token, _usdc_token, _btc_token, _oracle, _router) = setup_test_environment();
+
+        let heir = Address::generate(&env);
+        let period = 365 * 86_400;
+        let plan = vec![&env, Beneficiary { address: heir.clone(), share_bps: 10_000 }];
+
+        assert_eq!(client.try_set_beneficiaries(&user, &plan, &period), Err(Ok(Error::PortfolioNotFound)));
         client.deposit(&user, &500_000_000_000);
 
-        // Get initial portfolio
-        let initial_portfolio = client.get_portfolio(&user);
+        let short = vec![&env, Beneficiary { address: heir.clone(), share_bps: 9_000 }];
+        assert_eq!(client.try_set_beneficiaries(&user, &short, &period), Err(Ok(Error::InvalidBeneficiaries)));
+        let to_self = vec![&env, Beneficiary { address: user.clone(), share_bps: 10_000 }];
+        assert_eq!(client.try_set_beneficiaries(&user, &to_self, &period), Err(Ok(Error::InvalidBeneficiaries)));
+        assert_eq!(client.try_set_beneficiaries(&user, &plan, &86_400), Err(Ok(Error::InvalidBeneficiaries)));
 
-        // Rebalance (should not change anything as price movement is within threshold)
-        client.rebalance(&user);
+        client.set_beneficiaries(&user, &plan, &period);
+        assert_eq!(client.get_beneficiaries(&user).unwrap().beneficiaries, plan);
 
-        // Portfolio should remain the same
-        let final_portfolio = client.get_portfolio(&user);
-        assert_eq!(initial_portfolio.kale_balance, final_portfolio.kale_balance);
-        assert_eq!(initial_portfolio.usdc_balance, final_portfolio.usdc_balance);
-        assert_eq!(initial_portfolio.btc_balance, final_portfolio.btc_balance);
+        client.set_beneficiaries(&user, &vec![&env], &0);
+        assert_eq!(client.get_beneficiaries(&user), None);
     }
 
     #[test]
-    #[should_panic(expected = "Portfolio not found")]
-    fn test_rebalance_without_portfolio() {
-        let (_env, client, _admin, user, _kale_token, _usdc_token, _btc_token, _oracle, _router) = setup_test_environment();
+    fn test_claim_inheritance_after_inactivity() {
+        let (env, client, _admin, user, kale_token, usdc_token, _btc_token, _oracle, _router) = setup_test_environment();
+
+        // Lock-up does not stop inheritance
+        let reserve = Address::generate(&env);
+        client.set_retirement_policy(&(30 * 365 * 86_400), &1_000, &reserve);
+
+        let spouse = Address::generate(&env);
+        let child = Address::generate(&env);
+        let period = 365 * 86_400;
+        client.deposit(&user, &1_000_000_000_000);
+        client.deposit_asset(&user, &usdc_token, &100_000_000_001);
+        client.set_beneficiaries(
+            &user,
+            &vec![
+                &env,
+                Beneficiary { address: spouse.clone(), share_bps: 6_000 },
+                Beneficiary { address: child.clone(), share_bps: 4_000 },
+            ],
+            &period,
+        );
+
+        let stranger = Address::generate(&env);
+        assert_eq!(client.try_claim_inheritance(&stranger, &user), Err(Ok(Error::NotBeneficiary)));
+        assert_eq!(client.try_claim_inheritance(&spouse, &user), Err(Ok(Error::MemberStillActive)));
+
+        // Any member action restarts the timer
+        env.ledger().with_mut(|l| l.timestamp += period - 1);
+        client.check_in(&user);
+        env.ledger().with_mut(|l| l.timestamp += period - 1);
+        assert_eq!(client.try_claim_inheritance(&child, &user), Err(Ok(Error::MemberStillActive)));
+
+        env.ledger().with_mut(|l| l.timestamp += 1);
+        client.claim_inheritance(&child, &user);
+
+        let kale = token::Client::new(&env, &kale_token);
+        let usdc = token::Client::new(&env, &usdc_token);
+        assert_eq!(kale.balance(&spouse), 600_000_000_000);
+        assert_eq!(kale.balance(&child), 400_000_000_000);
+        assert_eq!(usdc.balance(&spouse), 60_000_000_000);
+        assert_eq!(usdc.balance(&child), 40_000_000_001);
+        assert_eq!(client.get_portfolio(&user).balance(&kale_token), 0);
+        assert_eq!(client.get_beneficiaries(&user), None);
+    }
 
-        client.rebalance(&user);
+    fn employer_terms() -> EmployerTerms {
+        EmployerTerms {
+            match_bps: 5_000,
+            match_cap: 300_000_000_000,
+            vesting_cliff: 365 * 86_400,
+            vesting_period: 4 * 365 * 86_400,
+        }
     }
 
     #[test]
-    fn test_withdraw() {
-        let (env, client, admin, user, kale_token, usdc_token, btc_token, _oracle, _router) = setup_test_environment();
+    fn test_employer_registry() {
+        let (env, client, _admin, user, _kale_token, usdc_token, _btc_token, _oracle, _router) = setup_test_environment();
+
+        let employer = Address::generate(&env);
+        assert_eq!(
+            client.try_contribute_for(&employer, &user, &usdc_token, &1_000_000_000),
+            Err(Ok(Error::EmployerNotRegistered))
+        );
+        assert_eq!(client.try_set_employer_terms(&employer, &employer_terms()), Err(Ok(Error::EmployerNotRegistered)));
+
+        let mut bad = employer_terms();
+        bad.vesting_cliff = bad.vesting_period + 1;
+        assert_eq!(client.try_register_employer(&employer, &bad), Err(Ok(Error::InvalidConfig)));
+        bad = employer_terms();
+        bad.match_bps = 10_001;
+        assert_eq!(client.try_register_employer(&employer, &bad), Err(Ok(Error::InvalidConfig)));
+
+        client.register_employer(&employer, &employer_terms());
+        assert_eq!(
+            env.events().all().slice(env.events().all().len() - 1..),
+            vec![
+                &env,
+                (
+                    client.address.clone(),
+                    (events::EMPLOYER, employer.clone()).into_val(&env),
+                    employer_terms().into_val(&env)
+                ),
+            ]
+        );
+
+        let mut terms = employer_terms();
+        terms.match_bps = 10_000;
+        client.set_employer_terms(&employer, &terms);
+        assert_eq!(client.get_employer(&employer), Some(terms));
+
+        client.remove_employer(&employer);
+        assert_eq!(client.get_employer(&employer), None);
+        assert_eq!(client.try_remove_employer(&employer), Err(Ok(Error::EmployerNotRegistered)));
+    }
 
-        // Deposit KALE
-        client.deposit(&user, &500_000_000_000);
+    #[test]
+    fn test_employer_match_and_vesting() {
+        let (env, client, _admin, user, _kale_token, usdc_token, _btc_token, _oracle, _router) = setup_test_environment();
 
-        // Verify portfolio has balance
-        let portfolio_before = client.get_portfolio(&user);
-        assert_eq!(portfolio_before.kale_balance, 500_000_000_000);
+        let employer = Address::generate(&env);
+        let usdc_admin = token::StellarAssetClient::new(&env, &usdc_token);
+        usdc_admin.mint(&employer, &10_000_000_000_000);
+        client.register_employer(&employer, &employer_terms());
 
-        // Withdraw (token transfers are mocked in tests)
-        client.withdraw(&user);
+        // 50% match on 1,000 USDC of own deposits, capped at 300 USDC
+        client.deposit_asset(&user, &usdc_token, &1_000_000_000_000);
+        client.contribute_for(&employer, &user, &usdc_token, &100_000_000_000);
+        let portfolio = client.get_portfolio(&user);
+        assert_eq!(portfolio.balance(&usdc_token), 1_000_000_000_000);
+        assert_eq!(portfolio.employer_shares, 400_000_000_000);
+        assert_eq!(client.get_employment(&user).unwrap().matched_value, 300_000_000_000);
+
+        // Another employer cannot fund the same employee
+        let other = Address::generate(&env);
+        client.register_employer(&other, &employer_terms());
+        assert_eq!(
+            client.try_contribute_for(&other, &user, &usdc_token, &1_000_000_000),
+            Err(Ok(Error::EmployedElsewhere))
+        );
+
+        // Nothing vests before the cliff
+        env.ledger().with_mut(|l| l.timestamp += 365 * 86_400 - 1);
+        assert_eq!(client.get_portfolio(&user).employer_shares, 400_000_000_000);
+
+        // Half vested after two years; new funding is vested at the same rate
+        env.ledger().with_mut(|l| l.timestamp += 365 * 86_400 + 1);
+        client.check_in(&user);
+        let portfolio = client.get_portfolio(&user);
+        assert_eq!(portfolio.vested_bps, 5_000);
+        assert_eq!(portfolio.balance(&usdc_token), 1_200_000_000_000);
+        assert_eq!(portfolio.employer_shares, 200_000_000_000);
 
-        // Portfolio should be empty
+        client.contribute_for(&employer, &user, &usdc_token, &100_000_000_000);
         let portfolio = client.get_portfolio(&user);
-        assert_eq!(portfolio.kale_balance, 0);
-        assert_eq!(portfolio.usdc_balance, 0);
-        assert_eq!(portfolio.btc_balance, 0);
-        assert_eq!(portfolio.risk_level, 1); // Default for new portfolio
+        assert_eq!(portfolio.balance(&usdc_token), 1_250_000_000_000);
+        assert_eq!(portfolio.employer_shares, 250_000_000_000);
+
+        // Leaving early returns unvested funding to the employer
+        let employer_before = token::Client::new(&env, &usdc_token).balance(&employer);
+        client.early_withdraw(&user);
+        assert_eq!(token::Client::new(&env, &usdc_token).balance(&employer), employer_before + 250_000_000_000);
+        assert_eq!(client.get_employment(&user).unwrap().matched_value, 300_000_000_000);
+    }
+
+    #[test]
+    fn test_employer_match_follows_net_contributions() {
+        let (env, client, _admin, user, _kale_token, usdc_token, _btc_token, _oracle, _router) = setup_test_environment();
+
+        let employer = Address::generate(&env);
+        let usdc = token::Client::new(&env, &usdc_token);
+        token::StellarAssetClient::new(&env, &usdc_token).mint(&employer, &10_000_000_000_000);
+        let mut terms = employer_terms();
+        terms.vesting_cliff = 0;
+        terms.vesting_period = 0;
+        client.register_employer(&employer, &terms);
+
+        // 50% match on 200 USDC of own deposits
+        client.deposit_asset(&user, &usdc_token, &2_000_000_000);
+        client.contribute_for(&employer, &user, &usdc_token, &100_000_000);
+        assert_eq!(client.get_employment(&user).unwrap().matched_value, 1_000_000_000);
+        assert_eq!(client.get_portfolio(&user).own_contributions, 2_000_000_000);
+
+        // Withdrawing half the portfolio releases half the own contributions, so
+        // depositing the same funds again earns no further match
+        client.withdraw_value(&user, &1_550_000_000);
+        assert_eq!(client.get_portfolio(&user).own_contributions, 1_000_000_000);
+        client.deposit_asset(&user, &usdc_token, &1_000_000_000);
+        let employer_before = usdc.balance(&employer);
+        client.contribute_for(&employer, &user, &usdc_token, &100_000_000);
+        assert_eq!(usdc.balance(&employer), employer_before - 100_000_000);
+
+        // Nor does leaving the fund and joining again
+        client.early_withdraw(&user);
+        client.deposit_asset(&user, &usdc_token, &2_000_000_000);
+        let employer_before = usdc.balance(&employer);
+        client.contribute_for(&employer, &user, &usdc_token, &100_000_000);
+        assert_eq!(usdc.balance(&employer), employer_before - 100_000_000);
+        assert_eq!(client.get_employment(&user).unwrap().matched_value, 1_000_000_000);
+    }
+
+    #[test]
+    fn test_contribute_batch() {
+        let (env, client, _admin, _user, _kale_token, usdc_token, _btc_token, _oracle, _router) = setup_test_environment();
+
+        let employer = Address::generate(&env);
+        token::StellarAssetClient::new(&env, &usdc_token).mint(&employer, &10_000_000_000_000);
+        let mut terms = employer_terms();
+        terms.vesting_cliff = 0;
+        terms.vesting_period = 0;
+        client.register_employer(&employer, &terms);
+
+        let alice = Address::generate(&env);
+        let bob = Address::generate(&env);
+        client.contribute_batch(
+            &employer,
+            &usdc_token,
+            &vec![
+                &env,
+                Contribution { employee: alice.clone(), amount: 10_000_000_000 },
+                Contribution { employee: bob.clone(), amount: 20_000_000_000 },
+            ],
+        );
+
+        // Immediate vesting and no own deposits, so no match
+        assert_eq!(client.get_portfolio(&alice).balance(&usdc_token), 10_000_000_000);
+        assert_eq!(client.get_portfolio(&bob).balance(&usdc_token), 20_000_000_000);
+        assert_eq!(client.get_portfolio(&bob).employer_shares, 0);
+
+        assert_eq!(
+            client.try_contribute_batch(
+                &employer,
+                &usdc_token,
+                &vec![&env, Contribution { employee: alice.clone(), amount: 0 }],
+            ),
+            Err(Ok(Error::InvalidAmount))
+        );
     }
 
     #[test]
     fn test_withdraw_without_portfolio() {
-        let (_env, client, _admin, user, _kale_token, _usdc_token, _btc_token, _oracle, _router) = setup_test_environment();
+        let (_env, client, _admin, user, kale_token, _usdc_token, _btc_token, _oracle, _router) = setup_test_environment();
 
-        // Should handle gracefully without panicking
-        client.withdraw(&user);
+        let result = client.try_withdraw(&user);
+        assert_eq!(result, Err(Ok(Error::PortfolioNotFound)));
         
         // Verify no portfolio exists after trying to withdraw without one
         let portfolio = client.get_portfolio(&user);
-        assert_eq!(portfolio.kale_balance, 0);
+        assert_eq!(portfolio.balance(&kale_token), 0);
     }
 
     #[test]
     fn test_complete_user_flow() {
-        let (env, client, admin, user, kale_token, _usdc_token, _btc_token, _oracle, _router) = setup_test_environment();
+        let (env, client, _admin, user, kale_token, usdc_token, btc_token, _oracle, _router) = setup_test_environment();
+        setup_mock_router(&env, &client, &kale_token, &usdc_token, &btc_token);
 
         // 1. Start with empty portfolio
         let portfolio = client.get_portfolio(&user);
-        assert_eq!(portfolio.kale_balance, 0);
+        assert_eq!(portfolio.balance(&kale_token), 0);
 
         // 2. Deposit KALE
         client.deposit(&user, &500_000_000_000);
         let portfolio = client.get_portfolio(&user);
-        assert_eq!(portfolio.kale_balance, 500_000_000_000);
+        assert_eq!(portfolio.balance(&kale_token), 500_000_000_000);
         assert_eq!(portfolio.risk_level, 1);
 
         // 3. Change risk profile
@@ -1257,18 +5241,20 @@ mod tests {
         let portfolio = client.get_portfolio(&user);
         assert_eq!(portfolio.risk_level, 3);
 
-        // 4. Rebalance (should work without errors)
+        // 4. Rebalance into the aggressive targets
         client.rebalance(&user);
+        let portfolio = client.get_portfolio(&user);
+        assert_eq!(portfolio.balance(&kale_token), 150_000_000_000);
 
         // 5. Withdraw everything
         client.withdraw(&user);
         let portfolio = client.get_portfolio(&user);
-        assert_eq!(portfolio.kale_balance, 0);
+        assert_eq!(portfolio.balance(&kale_token), 0);
     }
 
     #[test]
     fn test_multiple_users() {
-        let (env, client, admin, _user, kale_token, usdc_token, btc_token, _oracle, _router) = setup_test_environment();
+        let (env, client, _admin, _user, kale_token, usdc_token, btc_token, _oracle, _router) = setup_test_environment();
 
         let user1 = Address::generate(&env);
         let user2 = Address::generate(&env);
@@ -1289,10 +5275,10 @@ mod tests {
         let portfolio1 = client.get_portfolio(&user1);
         let portfolio2 = client.get_portfolio(&user2);
 
-        assert_eq!(portfolio1.kale_balance, 300_000_000_000);
+        assert_eq!(portfolio1.balance(&kale_token), 300_000_000_000);
         assert_eq!(portfolio1.risk_level, 1);
 
-        assert_eq!(portfolio2.kale_balance, 800_000_000_000);
+        assert_eq!(portfolio2.balance(&kale_token), 800_000_000_000);
         assert_eq!(portfolio2.risk_level, 3);
 
         // User1 withdraws, should not affect user2
@@ -1301,7 +5287,7 @@ mod tests {
         let portfolio1_after = client.get_portfolio(&user1);
         let portfolio2_after = client.get_portfolio(&user2);
 
-        assert_eq!(portfolio1_after.kale_balance, 0);
-        assert_eq!(portfolio2_after.kale_balance, 800_000_000_000); // Unchanged
+        assert_eq!(portfolio1_after.balance(&kale_token), 0);
+        assert_eq!(portfolio2_after.balance(&kale_token), 800_000_000_000); // Unchanged
     }
 }
@@ -0,0 +1,97 @@
+//! Overflow-checked fixed-point arithmetic for valuations.
+//!
+//! Prices, values and shares are `i128` fixed-point numbers. Products such as a
+//! BTC balance times its price can exceed `i128` long before the final result
+//! does, so `mul_div` keeps the full 256-bit product before dividing and rounds
+//! in the direction the caller asks for. Every failure is an `Error` rather than
+//! a wrapped result or a panic.
+
+use crate::Error;
+
+// Direction a result that falls between two integers is rounded in
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Rounding {
+    Down, // Towards negative infinity
+    Up,   // Towards positive infinity
+}
+
+// a * b / denominator, rounded as asked
+pub fn mul_div(a: i128, b: i128, denominator: i128, rounding: Rounding) -> Result<i128, Error> {
+    if denominator == 0 {
+        return Err(Error::DivisionByZero);
+    }
+
+    let (high, low) = widening_mul(a.unsigned_abs(), b.unsigned_abs());
+    let (quotient, remainder) = div_rem(high, low, denominator.unsigned_abs()).ok_or(Error::Overflow)?;
+    let negative = ((a < 0) != (b < 0)) != (denominator < 0);
+
+    // The magnitude grows when rounding away from zero
+    let round_away = remainder != 0 && (rounding == Rounding::Up) != negative;
+    let magnitude = if round_away { quotient.checked_add(1).ok_or(Error::Overflow)? } else { quotient };
+    if negative {
+        0i128.checked_sub_unsigned(magnitude).ok_or(Error::Overflow)
+    } else {
+        i128::try_from(magnitude).map_err(|_| Error::Overflow)
+    }
+}
+
+pub fn mul_div_down(a: i128, b: i128, denominator: i128) -> Result<i128, Error> {
+    mul_div(a, b, denominator, Rounding::Down)
+}
+
+pub fn mul_div_up(a: i128, b: i128, denominator: i128) -> Result<i128, Error> {
+    mul_div(a, b, denominator, Rounding::Up)
+}
+
+pub fn add(a: i128, b: i128) -> Result<i128, Error> {
+    a.checked_add(b).ok_or(Error::Overflow)
+}
+
+pub fn mul(a: i128, b: i128) -> Result<i128, Error> {
+    a.checked_mul(b).ok_or(Error::Overflow)
+}
+
+pub fn pow10(exponent: u32) -> Result<i128, Error> {
+    10i128.checked_pow(exponent).ok_or(Error::Overflow)
+}
+
+// Full 256-bit product as (high, low) halves
+fn widening_mul(a: u128, b: u128) -> (u128, u128) {
+    const MASK: u128 = u64::MAX as u128;
+
+    let (a_high, a_low) = (a >> 64, a & MASK);
+    let (b_high, b_low) = (b >> 64, b & MASK);
+    let low_low = a_low * b_low;
+    let high_low = a_high * b_low;
+    let low_high = a_low * b_high;
+    let high_high = a_high * b_high;
+
+    let middle = (low_low >> 64) + (high_low & MASK) + (low_high & MASK);
+    let low = (middle << 64) | (low_low & MASK);
+    let high = high_high + (high_low >> 64) + (low_high >> 64) + (middle >> 64);
+    (high, low)
+}
+
+// Quotient and remainder of a 256-bit number by a non-zero divisor, or None if the
+// quotient doesn't fit in 128 bits
+fn div_rem(high: u128, low: u128, divisor: u128) -> Option<(u128, u128)> {
+    if high >= divisor {
+        return None;
+    }
+    if high == 0 {
+        return Some((low / divisor, low % divisor));
+    }
+
+    // Long division one bit at a time; the remainder stays below the divisor throughout
+    let (mut quotient, mut remainder) = (0u128, high);
+    for bit in (0..128).rev() {
+        let carry = remainder >> 127;
+        remainder = (remainder << 1) | ((low >> bit) & 1);
+        quotient <<= 1;
+        if carry == 1 || remainder >= divisor {
+            remainder = remainder.wrapping_sub(divisor);
+            quotient |= 1;
+        }
+    }
+    Some((quotient, remainder))
+}
@@ -0,0 +1,173 @@
+//! SEP-41 token interface over members' pool shares.
+//!
+//! A member's token balance is the vested share count of their `Portfolio`, so
+//! wallets and other contracts can read fund positions like any Stellar token.
+//! Shares only enter and leave through deposits and withdrawals; moving them
+//! between holders is governed by the admin's `TransferPolicy` and burning is
+//! never allowed. Shares are priced in USDC base units, hence 7 decimals.
+
+use soroban_sdk::{contractimpl, contracttype, panic_with_error, token::TokenInterface, Address, Env, String};
+
+use crate::{events, DataKey, Error, KalePensionFund, KalePensionFundClient, Portfolio, TransferPolicy};
+
+const DECIMALS: u32 = 7;
+
+// Spending allowance granted by a share holder
+#[derive(Clone)]
+#[contracttype]
+pub struct ShareAllowance {
+    pub amount: i128,
+    pub expiration_ledger: u32,
+}
+
+#[contractimpl]
+impl TokenInterface for KalePensionFund {
+    fn allowance(env: Env, from: Address, spender: Address) -> i128 {
+        read_allowance(&env, &from, &spender)
+    }
+
+    fn approve(env: Env, from: Address, spender: Address, amount: i128, expiration_ledger: u32) {
+        from.require_auth();
+
+        if amount < 0 {
+            panic_with_error!(&env, Error::InvalidAmount);
+        }
+        if amount > 0 && expiration_ledger < env.ledger().sequence() {
+            panic_with_error!(&env, Error::InvalidConfig);
+        }
+
+        let key = DataKey::Allowance(from.clone(), spender.clone());
+        env.storage().temporary().set(&key, &ShareAllowance { amount, expiration_ledger });
+        if amount > 0 {
+            let live_for = expiration_ledger - env.ledger().sequence();
+            env.storage().temporary().extend_ttl(&key, live_for, live_for);
+        }
+
+        events::shares_approved(&env, &from, &spender, amount, expiration_ledger);
+    }
+
+    // A pure read: shares vested since the member's last action count, but are not released
+    fn balance(env: Env, id: Address) -> i128 {
+        let Some(portfolio) = KalePensionFund::stored_portfolio(&env, &id) else {
+            return 0;
+        };
+        let vested = KalePensionFund::newly_vested(&env, &portfolio).map_or(0, |(shares, _)| shares);
+        portfolio.shares + vested
+    }
+
+    fn transfer(env: Env, from: Address, to: Address, amount: i128) {
+        from.require_auth();
+
+        if let Err(error) = transfer_shares(&env, &from, &to, amount, true) {
+            panic_with_error!(&env, error);
+        }
+    }
+
+    fn transfer_from(env: Env, spender: Address, from: Address, to: Address, amount: i128) {
+        spender.require_auth();
+
+        let result = spend_allowance(&env, &from, &spender, amount)
+            .and_then(|_| transfer_shares(&env, &from, &to, amount, false));
+        if let Err(error) = result {
+            panic_with_error!(&env, error);
+        }
+    }
+
+    // Burning would hand the burned value to the rest of the pool; shares only leave through withdrawals
+    fn burn(env: Env, from: Address, _amount: i128) {
+        from.require_auth();
+        panic_with_error!(&env, Error::TransfersDisabled);
+    }
+
+    fn burn_from(env: Env, spender: Address, _from: Address, _amount: i128) {
+        spender.require_auth();
+        panic_with_error!(&env, Error::TransfersDisabled);
+    }
+
+    fn decimals(_env: Env) -> u32 {
+        DECIMALS
+    }
+
+    fn name(env: Env) -> String {
+        String::from_str(&env, "KALE Pension Fund Share")
+    }
+
+    fn symbol(env: Env) -> String {
+        String::from_str(&env, "KPFS")
+    }
+}
+
+fn read_allowance(env: &Env, from: &Address, spender: &Address) -> i128 {
+    let key = DataKey::Allowance(from.clone(), spender.clone());
+    match env.storage().temporary().get::<DataKey, ShareAllowance>(&key) {
+        Some(allowance) if allowance.expiration_ledger >= env.ledger().sequence() => allowance.amount,
+        _ => 0,
+    }
+}
+
+fn spend_allowance(env: &Env, from: &Address, spender: &Address, amount: i128) -> Result<(), Error> {
+    let available = read_allowance(env, from, spender);
+    if amount > available {
+        return Err(Error::InsufficientAllowance);
+    }
+
+    let key = DataKey::Allowance(from.clone(), spender.clone());
+    if let Some(mut allowance) = env.storage().temporary().get::<DataKey, ShareAllowance>(&key) {
+        allowance.amount -= amount;
+        env.storage().temporary().set(&key, &allowance);
+    }
+    Ok(())
+}
+
+// Move vested shares between two holders of the same pool. The recipient can never
+// unlock them sooner than the sender could, so transfers cannot dodge the lock-up.
+// Only a transfer the sender signed counts as their activity, not a spender's.
+fn transfer_shares(env: &Env, from: &Address, to: &Address, amount: i128, by_owner: bool) -> Result<(), Error> {
+    if amount < 0 {
+        return Err(Error::InvalidAmount);
+    }
+
+    let policy = KalePensionFund::get_share_transfer_policy(env.clone());
+    if policy == TransferPolicy::Disabled {
+        return Err(Error::TransfersDisabled);
+    }
+
+    let mut sender = KalePensionFund::load_portfolio(env, from)?;
+    if sender.shares < amount {
+        return Err(Error::InsufficientBalance);
+    }
+    if from == to {
+        events::shares_transferred(env, from, to, amount);
+        return Ok(());
+    }
+
+    let mut recipient = match KalePensionFund::load_portfolio(env, to) {
+        Ok(recipient) => recipient,
+        Err(_) if policy == TransferPolicy::Open => {
+            let mut recipient = Portfolio::new(to.clone());
+            recipient.risk_level = sender.risk_level;
+            recipient.retirement_date = KalePensionFund::earliest_retirement_date(env).max(sender.retirement_date);
+            events::retirement_date_set(env, to, 0, recipient.retirement_date);
+            recipient
+        }
+        Err(error) => return Err(error),
+    };
+    if recipient.risk_level != sender.risk_level {
+        return Err(Error::RiskTierMismatch);
+    }
+    if recipient.retirement_date < sender.retirement_date {
+        return Err(Error::RetirementLocked);
+    }
+
+    let pool = KalePensionFund::load_pool(env, sender.risk_level)?;
+    sender.remove_shares(amount)?;
+    recipient.shares += amount;
+    if by_owner {
+        KalePensionFund::touch(env, &mut sender);
+    }
+    KalePensionFund::save_portfolio(env, &mut sender, &pool)?;
+    KalePensionFund::save_portfolio(env, &mut recipient, &pool)?;
+
+    events::shares_transferred(env, from, to, amount);
+    Ok(())
+}